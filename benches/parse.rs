@@ -0,0 +1,78 @@
+// Copyright (C) 2024 Ethan Uppal. All rights reserved.
+
+use std::{fmt::Write, path::Path};
+
+use asmdoc::{
+    assembly_file::AssemblyFile,
+    syntax::{Syntax, NASM}
+};
+use criterion::{
+    criterion_group, criterion_main, BenchmarkId, Criterion, Throughput
+};
+
+/// A NASM source of at least `size` bytes exercising most of the grammar.
+fn generate(size: usize) -> String {
+    let mut source = String::from("bits 64\n%include \"macros.nasm\"\n");
+    let mut i = 0;
+    while source.len() < size {
+        write!(
+            source,
+            "
+section .data
+;; Message number {i}.
+message_{i} db `hello {i}\\n`, 0
+table_{i}: times 4 dq 0x{i:x}, 1100b
+
+section .text
+global routine_{i}
+extern helper_{i}
+%define LIMIT_{i} {i}
+
+;; Adds things.
+;; @param rdi the first
+;; @clobbers rax
+routine_{i}:
+    push rbx
+    mov rax, [rdi + 8 * rsi]
+    lea rbx, [rel message_{i}]
+    rep movsb
+    call helper_{i}
+    cmp rax, LIMIT_{i} ; compare
+    jne .done
+    $print rax
+.done:
+    pop rbx
+    ret
+"
+        )
+        .unwrap();
+        i += 1;
+    }
+    source
+}
+
+fn parse(c: &mut Criterion) {
+    let file = Path::new("bench.nasm");
+    let mut group = c.benchmark_group("nasm");
+    group.sample_size(10);
+    for megabytes in [1, 4] {
+        let source = generate(megabytes << 20);
+        group.throughput(Throughput::Bytes(source.len() as u64));
+        group.bench_with_input(
+            BenchmarkId::new("lex", megabytes),
+            &source,
+            |b, source| b.iter(|| NASM::new_parser(file, source).unwrap())
+        );
+        group.bench_with_input(
+            BenchmarkId::new("parse", megabytes),
+            &source,
+            |b, source| {
+                b.iter(|| AssemblyFile::parse::<NASM>(file, source).unwrap())
+            }
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, parse);
+criterion_main!(benches);
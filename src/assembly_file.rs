@@ -1,21 +1,24 @@
 // Copyright (C) 2024 Ethan Uppal. All rights reserved
 
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeSet, HashMap},
     fmt::{self, Display},
     path::{Path, PathBuf}
 };
 
-use serde::Serialize;
+use linked_hash_map::LinkedHashMap;
+use serde::{Deserialize, Serialize};
 
-use crate::syntax::Syntax;
+use crate::{register::Register, syntax::Syntax};
 
-#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug, Serialize)]
+#[derive(PartialEq, Eq, Hash, Clone, Debug, Serialize, Deserialize)]
 pub enum AssemblySection {
     Text,
     Data,
     BSS,
-    ROData
+    ROData,
+    /// Any other section, by name as written (e.g., `.init`).
+    Custom(String)
 }
 
 impl Display for AssemblySection {
@@ -24,36 +27,235 @@ impl Display for AssemblySection {
             AssemblySection::Text => "text",
             AssemblySection::Data => "data",
             AssemblySection::BSS => "bss",
-            AssemblySection::ROData => "read-only data"
+            AssemblySection::ROData => "read-only data",
+            AssemblySection::Custom(name) => name
         }
         .fmt(f)
     }
 }
 
-#[derive(Debug, Serialize)]
+/// Properties of a section as declared (or implied by its name).
+#[derive(PartialEq, Eq, Clone, Debug, Serialize, Deserialize)]
+pub struct SectionAttributes {
+    /// Alignment in bytes, if any.
+    pub align: Option<usize>,
+    /// Whether the section occupies memory at run time.
+    pub alloc: bool,
+    pub exec: bool,
+    pub write: bool,
+    /// Whether the section has no contents in the file, like `.bss`.
+    pub nobits: bool
+}
+
+impl SectionAttributes {
+    /// The attributes a section has when declared without any, following
+    /// the ELF conventions for the standard section names.
+    pub fn default_for(section: &AssemblySection) -> Self {
+        let (align, exec, write, nobits) = match section {
+            AssemblySection::Text => (16, true, false, false),
+            AssemblySection::Data => (4, false, true, false),
+            AssemblySection::BSS => (4, false, true, true),
+            AssemblySection::ROData => (4, false, false, false),
+            AssemblySection::Custom(_) => (1, false, false, false)
+        };
+        Self {
+            align: Some(align),
+            alloc: true,
+            exec,
+            write,
+            nobits
+        }
+    }
+}
+
+impl Display for SectionAttributes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut flags = Vec::new();
+        if let Some(align) = self.align {
+            flags.push(format!("aligned to {}", align));
+        }
+        if !self.alloc {
+            flags.push("not allocated".into());
+        }
+        if self.nobits {
+            flags.push("uninitialized".into());
+        }
+        flags.push(if self.write { "writable" } else { "read-only" }.into());
+        if self.exec {
+            flags.push("executable".into());
+        }
+        flags.join(", ").fmt(f)
+    }
+}
+
+/// Storage declared by a data directive, e.g., `db "hello", 10` or `resq 4`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DataLayout {
+    /// The directive as written, e.g., `db` or `resq`.
+    pub directive: String,
+    /// Width in bytes of a single element.
+    pub width: usize,
+    /// Number of elements, if it can be determined without evaluating
+    /// expressions.
+    pub count: Option<usize>,
+    /// Initial values as written, or the reserved count for `res*`.
+    pub values: Vec<String>,
+    /// Whether the storage is reserved (uninitialized) rather than defined.
+    pub reserved: bool,
+    /// The full directive as written, including any `times` prefix.
+    pub source: String
+}
+
+impl DataLayout {
+    /// Total size in bytes, if known.
+    pub fn size(&self) -> Option<usize> {
+        self.count.map(|count| count * self.width)
+    }
+}
+
+/// An instruction operand.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Operand {
+    Register(Register),
+    /// A memory reference, with the registers used to compute its address.
+    Memory(Vec<Register>),
+    /// Anything else, e.g., an immediate or a label, as written.
+    Other(String)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Instruction {
+    /// A prefix such as `rep` or `lock`, if any.
+    pub prefix: Option<String>,
+    /// The lowercase mnemonic, e.g., `mov`.
+    pub mnemonic: String,
+    pub operands: Vec<Operand>
+}
+
+/// A documentation comment attached to a label, macro, or define.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DocComment {
+    /// Lines of prose, in order.
+    pub text: Vec<String>,
+    /// Tags such as `@clobbers rax, rcx`, as (tag, contents) pairs in order.
+    pub tags: Vec<(String, String)>
+}
+
+impl DocComment {
+    /// Splits the lines of a documentation comment (with comment markers
+    /// already removed) into prose and `@tag` lines.
+    pub fn parse<'a>(lines: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut doc = Self::default();
+        for line in lines {
+            let line = line.trim();
+            if let Some(tag) = line.strip_prefix('@') {
+                let (tag, contents) =
+                    tag.split_once(char::is_whitespace).unwrap_or((tag, ""));
+                doc.tags
+                    .push((tag.to_string(), contents.trim().to_string()));
+            } else {
+                doc.text.push(line.to_string());
+            }
+        }
+        while doc.text.last().is_some_and(|line| line.is_empty()) {
+            doc.text.pop();
+        }
+        doc
+    }
+
+    /// The contents of every occurrence of `@tag`.
+    pub fn tag<'a>(&'a self, tag: &'a str) -> impl Iterator<Item = &'a str> {
+        self.tags
+            .iter()
+            .filter(move |(name, _)| name == tag)
+            .map(|(_, contents)| contents.as_str())
+    }
+
+    /// The full registers named by every occurrence of `@tag`, e.g., `rax`
+    /// and `rcx` for `@clobbers eax, rcx`. Words that are not registers are
+    /// ignored.
+    pub fn tag_registers(&self, tag: &str) -> Vec<Register> {
+        let mut registers = Vec::new();
+        for register in self
+            .tag(tag)
+            .flat_map(|contents| contents.split([',', ' ', '\t']))
+            .filter_map(Register::parse)
+            .map(|register| register.full())
+        {
+            if !registers.contains(&register) {
+                registers.push(register);
+            }
+        }
+        registers
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
 pub enum AssemblyItem {
     Label(String),
-    Mnemonic(),
+    Mnemonic(Instruction),
+    Data(DataLayout),
     MacroCall(String, Vec<Box<AssemblyItem>>)
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct AssemblyMacro {
     pub name: String,
     pub arg_count: usize,
     pub body: Vec<AssemblyItem>
 }
 
+/// A structure type declared with `struc`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AssemblyStruct {
+    pub name: String,
+    /// Fields in order, by name (`None` for padding).
+    pub fields: Vec<(Option<String>, DataLayout)>
+}
+
+impl AssemblyStruct {
+    /// Total size in bytes, if known.
+    pub fn size(&self) -> Option<usize> {
+        self.fields.iter().map(|(_, layout)| layout.size()).sum()
+    }
+}
+
+/// Line numbers of declarations, by name, for diagnostics and tags.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct DeclarationLines {
+    /// Non-local labels.
+    pub labels: HashMap<String, usize>,
+    /// Local labels, by full name (e.g., `strlen.loop`).
+    pub locals: HashMap<String, usize>,
+    pub globals: HashMap<String, usize>,
+    pub externs: HashMap<String, usize>,
+    pub macros: HashMap<String, usize>,
+    /// Defines and `equ` constants.
+    pub defines: HashMap<String, usize>,
+    pub structs: HashMap<String, usize>,
+    /// Structure fields, by full name (e.g., `point.x`).
+    pub fields: HashMap<String, usize>
+}
+
 /// Assembly file representation optimized for documentation generation.
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct AssemblyFile {
     pub bits: usize,
     pub includes: Vec<PathBuf>,
-    pub globals: HashSet<String>,
+    pub globals: BTreeSet<String>,
     pub externs: Vec<String>,
     pub macros: Vec<AssemblyMacro>,
     pub defines: Vec<String>,
-    pub sections: HashMap<AssemblySection, Vec<AssemblyItem>>
+    pub structs: Vec<AssemblyStruct>,
+    /// Items of each section, with sections in order of first appearance.
+    pub sections: LinkedHashMap<AssemblySection, Vec<AssemblyItem>>,
+    pub section_attributes: LinkedHashMap<AssemblySection, SectionAttributes>,
+    /// Symbols used in operands, data, constants, and macro arguments.
+    pub references: BTreeSet<String>,
+    pub lines: DeclarationLines,
+    /// Documentation comments, by the name of the label, macro, define, or
+    /// structure they document.
+    pub docs: HashMap<String, DocComment>
 }
 
 impl Default for AssemblyFile {
@@ -61,11 +263,16 @@ impl Default for AssemblyFile {
         Self {
             bits: 64,
             includes: Vec::new(),
-            globals: HashSet::new(),
+            globals: BTreeSet::new(),
             externs: Vec::new(),
             macros: Vec::new(),
             defines: Vec::new(),
-            sections: HashMap::new()
+            structs: Vec::new(),
+            sections: LinkedHashMap::new(),
+            section_attributes: LinkedHashMap::new(),
+            references: BTreeSet::new(),
+            lines: DeclarationLines::default(),
+            docs: HashMap::new()
         }
     }
 }
@@ -1,136 +1,764 @@
 // Copyright (C) 2024 Ethan Uppal. All  rights reserved.
 
-use std::{collections::HashMap, path::PathBuf};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    path::{Component, Path, PathBuf}
+};
 
 use linked_hash_map::LinkedHashMap;
 
 use crate::{
-    assembly_file::{AssemblyFile, AssemblyItem, AssemblySection},
-    docs::{Docs, Visibility}
+    assembly_file::{
+        AssemblyFile, AssemblyItem, AssemblyMacro, AssemblySection, DataLayout,
+        DocComment, Instruction, SectionAttributes
+    },
+    docs::{Docs, Visibility},
+    layout::{Layout, Placement},
+    library::Libraries,
+    register_usage::RegisterUsage
 };
 
+/// A symbol as resolved against the rest of the project.
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    pub visibility: Visibility,
+    /// `None` for externs.
+    pub section: Option<AssemblySection>,
+    /// Data directives following the label, in source order.
+    pub data: Vec<DataLayout>,
+    pub doc: Option<DocComment>,
+    /// Register usage, if the label begins a routine in an executable
+    /// section.
+    pub registers: Option<RegisterUsage>
+}
+
+impl Symbol {
+    /// Total size in bytes of the data following the label, if known.
+    pub fn data_size(&self) -> Option<usize> {
+        self.data.iter().map(DataLayout::size).sum()
+    }
+}
+
 #[derive(Default)]
 pub struct AssemblyProject {
-    files: HashMap<PathBuf, AssemblyFile>,
-    symbols: HashMap<
-        PathBuf,
-        LinkedHashMap<String, (Visibility, Option<AssemblySection>)>
-    >,
-    /// Location of project-defined globals.
-    global_sources: HashMap<String, PathBuf>,
-    /// Location of project-internal externs.
-    internal_externs: HashMap<String, PathBuf>,
-    symbol_constituents: HashMap<String, Vec<String>>
+    /// Sorted by path so that output does not depend on discovery order.
+    files: BTreeMap<PathBuf, AssemblyFile>,
+    symbols: HashMap<PathBuf, LinkedHashMap<String, Symbol>>,
+    /// Files defining each project-defined global, in path order. More than
+    /// one file means the definitions conflict.
+    global_sources: HashMap<String, Vec<PathBuf>>,
+    /// Local labels within each symbol, by file.
+    symbol_constituents: HashMap<PathBuf, HashMap<String, Vec<String>>>,
+    /// Directories searched for `%include`d files.
+    include_paths: Vec<PathBuf>,
+    libraries: Libraries
+}
+
+/// Where the definition of an extern comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExternSource<'a> {
+    /// Files of the project that define it.
+    Internal(&'a [PathBuf]),
+    /// A known library outside the project, by name.
+    Library(&'a str),
+    Unresolved
+}
+
+/// Lexically removes `.` and `..` components from `path`.
+pub(crate) fn normalize(path: &Path) -> PathBuf {
+    let mut normal = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir
+                if matches!(
+                    normal.components().next_back(),
+                    Some(Component::Normal(_))
+                ) =>
+            {
+                normal.pop();
+            }
+            component => normal.push(component)
+        }
+    }
+    normal
 }
 
 impl AssemblyProject {
-    pub fn build_from(files: HashMap<PathBuf, AssemblyFile>) -> Self {
+    pub fn build_from(
+        files: impl IntoIterator<Item = (PathBuf, AssemblyFile)>
+    ) -> Self {
         Self {
-            files,
+            files: files.into_iter().collect(),
             ..Default::default()
         }
         .resolve()
     }
 
+    /// The parsed files, by path.
+    pub fn files(&self) -> &BTreeMap<PathBuf, AssemblyFile> {
+        &self.files
+    }
+
+    /// The symbols of `file`, in source order, if it is part of the project.
+    pub fn symbols(
+        &self, file: &Path
+    ) -> Option<&LinkedHashMap<String, Symbol>> {
+        self.symbols.get(file)
+    }
+
+    /// The files that define `global`, in path order.
+    pub fn definitions(&self, global: &str) -> &[PathBuf] {
+        self.global_sources
+            .get(global)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// The globals defined in more than one file, by name, with the files
+    /// defining them.
+    pub fn duplicate_globals(&self) -> BTreeMap<&str, &[PathBuf]> {
+        self.global_sources
+            .iter()
+            .filter(|(_, files)| files.len() > 1)
+            .map(|(name, files)| (name.as_str(), files.as_slice()))
+            .collect()
+    }
+
+    /// Where the definition of the extern `name` comes from.
+    pub fn extern_source(&self, name: &str) -> ExternSource<'_> {
+        let definitions = self.definitions(name);
+        if !definitions.is_empty() {
+            ExternSource::Internal(definitions)
+        } else if let Some(library) = self.libraries.provider(name) {
+            ExternSource::Library(library)
+        } else {
+            ExternSource::Unresolved
+        }
+    }
+
+    /// The globals that `file` declares but does not define, in name order.
+    pub fn undefined_globals(&self, file: &Path) -> Vec<&str> {
+        let Some(asm) = self.files.get(file) else {
+            return Vec::new();
+        };
+        asm.globals
+            .iter()
+            .filter(|global| {
+                !asm.lines.labels.contains_key(*global)
+                    && !asm.defines.contains(global)
+            })
+            .map(String::as_str)
+            .collect()
+    }
+
+    /// The symbol `name` in `file`, if any.
+    pub fn symbol(&self, file: &Path, name: &str) -> Option<&Symbol> {
+        self.symbols.get(file)?.get(name)
+    }
+
+    /// Every definition of a label named `name`, global or private, in path
+    /// order. Externs are not definitions.
+    pub fn find_symbol(&self, name: &str) -> Vec<(&Path, &Symbol)> {
+        self.files
+            .keys()
+            .filter_map(|file| {
+                let symbol = self.symbol(file, name)?;
+                (symbol.visibility != Visibility::External)
+                    .then_some((file.as_path(), symbol))
+            })
+            .collect()
+    }
+
+    /// The sections of `file`, in order of first appearance.
+    pub fn sections(
+        &self, file: &Path
+    ) -> impl Iterator<Item = &AssemblySection> {
+        self.files
+            .get(file)
+            .into_iter()
+            .flat_map(|asm| asm.sections.keys())
+    }
+
+    /// The symbols `file` defines in `section`, in source order.
+    pub fn symbols_in_section<'a>(
+        &'a self, file: &Path, section: &'a AssemblySection
+    ) -> impl Iterator<Item = (&'a str, &'a Symbol)> {
+        self.symbols
+            .get(file)
+            .into_iter()
+            .flat_map(|symbols| symbols.iter())
+            .filter(move |(_, symbol)| symbol.section.as_ref() == Some(section))
+            .map(|(name, symbol)| (name.as_str(), symbol))
+    }
+
+    /// The global symbols that the extern `name` refers to, which are more
+    /// than one if the definitions conflict and none if `name` is provided by
+    /// a library or unresolved (see [`AssemblyProject::extern_source`]).
+    pub fn extern_definitions(&self, name: &str) -> Vec<(&Path, &Symbol)> {
+        self.definitions(name)
+            .iter()
+            .filter_map(|file| Some((file.as_path(), self.symbol(file, name)?)))
+            .collect()
+    }
+
+    /// The local labels (e.g., `.loop`) following the label `name` in `file`,
+    /// in source order.
+    pub fn local_labels(&self, file: &Path, name: &str) -> &[String] {
+        self.symbol_constituents
+            .get(file)
+            .and_then(|constituents| constituents.get(name))
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Every macro in the project, with the file defining it, in path order.
+    pub fn macros(&self) -> impl Iterator<Item = (&Path, &AssemblyMacro)> {
+        self.files.iter().flat_map(|(file, asm)| {
+            asm.macros
+                .iter()
+                .map(move |macro_| (file.as_path(), macro_))
+        })
+    }
+
+    /// Sets the libraries whose symbols externs may name, which by default
+    /// are those of libc.
+    pub fn with_libraries(mut self, libraries: Libraries) -> Self {
+        self.libraries = libraries;
+        self
+    }
+
+    /// Sets the directories searched for `%include`d files, after the
+    /// directory of the including file.
+    pub fn with_include_paths(mut self, include_paths: Vec<PathBuf>) -> Self {
+        self.include_paths = include_paths;
+        self
+    }
+
+    /// The project file that `include` in `file` refers to, if any.
+    pub fn resolve_include(
+        &self, file: &Path, include: &Path
+    ) -> Option<PathBuf> {
+        let candidates = file
+            .parent()
+            .into_iter()
+            .chain(self.include_paths.iter().map(PathBuf::as_path))
+            .map(|dir| dir.join(include))
+            .chain([include.to_path_buf()])
+            .map(|candidate| normalize(&candidate))
+            .collect::<Vec<_>>();
+        self.files
+            .keys()
+            .find(|project_file| candidates.contains(&normalize(project_file)))
+            .cloned()
+    }
+
     fn resolve(mut self) -> Self {
-        for (file, asm) in &self.files {
-            for global in &asm.globals {
-                self.global_sources.insert(global.clone(), file.clone());
+        self.resolve_globals();
+        let files = self.files.keys().cloned().collect();
+        self.resolve_files(&files);
+        self
+    }
+
+    /// Replaces each changed file with its new contents, or removes it if
+    /// `None`, then re-resolves the changed files and those referring to
+    /// labels they define or used to define.
+    pub fn update(
+        &mut self,
+        changes: impl IntoIterator<Item = (PathBuf, Option<AssemblyFile>)>
+    ) {
+        let mut changed = BTreeSet::new();
+        let mut labels = HashSet::new();
+        for (file, asm) in changes {
+            if let Some(old) = self.files.get(&file) {
+                labels.extend(old.lines.labels.keys().cloned());
             }
+            match asm {
+                Some(asm) => {
+                    labels.extend(asm.lines.labels.keys().cloned());
+                    self.files.insert(file.clone(), asm);
+                }
+                None => {
+                    self.files.remove(&file);
+                    self.symbols.remove(&file);
+                    self.symbol_constituents.remove(&file);
+                }
+            }
+            changed.insert(file);
         }
+
+        let affected = self
+            .files
+            .iter()
+            .filter(|(file, asm)| {
+                changed.contains(*file)
+                    || asm.references.iter().any(|name| labels.contains(name))
+            })
+            .map(|(file, _)| file.clone())
+            .collect();
+        self.resolve_globals();
+        self.resolve_files(&affected);
+    }
+
+    /// Finds the files defining each global.
+    fn resolve_globals(&mut self) {
+        self.global_sources.clear();
         for (file, asm) in &self.files {
-            for extern_ in &asm.externs {
-                if let Some(global_def_file) = self.global_sources.get(extern_)
+            for global in &asm.globals {
+                if asm.lines.labels.contains_key(global)
+                    || asm.defines.contains(global)
                 {
-                    self.internal_externs
-                        .insert(extern_.clone(), global_def_file.clone());
+                    self.global_sources
+                        .entry(global.clone())
+                        .or_default()
+                        .push(file.clone());
                 }
             }
+        }
+    }
+
+    /// Recomputes the symbols of `files` and the register usage of their
+    /// routines, then propagates clobbers through the whole project.
+    fn resolve_files(&mut self, files: &BTreeSet<PathBuf>) {
+        let mut routine_bodies = Vec::new();
+        for file in files {
+            let Some(asm) = self.files.get(file) else {
+                continue;
+            };
+            let (symbols, constituents, bodies) = Self::collect_symbols(asm);
+            self.symbols.insert(file.clone(), symbols);
+            self.symbol_constituents.insert(file.clone(), constituents);
+            routine_bodies.push((file, bodies));
+        }
 
-            let local_symbols = self.symbols.entry(file.clone()).or_default();
+        let routines = self
+            .symbols
+            .values()
+            .flat_map(|symbols| symbols.iter())
+            .filter(|(_, symbol)| symbol.registers.is_some())
+            .map(|(name, _)| name.clone())
+            .collect::<HashSet<_>>();
+        for (file, bodies) in routine_bodies {
+            let symbols = self.symbols.get_mut(file).unwrap();
+            for (name, body) in bodies {
+                if let Some(symbol) = symbols.get_mut(&name) {
+                    symbol.registers = Some(RegisterUsage::analyze(
+                        body.iter().copied(),
+                        |target| routines.contains(target)
+                    ));
+                }
+            }
+        }
 
-            for extern_ in &asm.externs {
-                local_symbols
-                    .insert(extern_.clone(), (Visibility::External, None));
+        // start from each routine's own clobbers so that stale ones from
+        // removed calls do not persist
+        for symbol in self
+            .symbols
+            .values_mut()
+            .flat_map(|symbols| symbols.iter_mut().map(|(_, symbol)| symbol))
+        {
+            if let Some(usage) = &mut symbol.registers {
+                usage.clobber(&[]);
             }
+        }
+        self.propagate_clobbers();
+    }
+
+    /// The symbols of `asm`, the local labels within each, and the
+    /// instructions of each routine. Routines get a placeholder register
+    /// usage until analyzed.
+    #[allow(clippy::type_complexity)]
+    fn collect_symbols(
+        asm: &AssemblyFile
+    ) -> (
+        LinkedHashMap<String, Symbol>,
+        HashMap<String, Vec<String>>,
+        Vec<(String, Vec<&Instruction>)>
+    ) {
+        let mut local_symbols = LinkedHashMap::new();
+        let mut constituents: HashMap<String, Vec<String>> = HashMap::new();
+        let mut routine_bodies: Vec<(String, Vec<&Instruction>)> = Vec::new();
+
+        for extern_ in &asm.externs {
+            local_symbols.insert(
+                extern_.clone(),
+                Symbol {
+                    visibility: Visibility::External,
+                    section: None,
+                    data: Vec::new(),
+                    doc: None,
+                    registers: None
+                }
+            );
+        }
 
-            let mut current_label = String::new();
-            for (section, items) in &asm.sections {
-                for item in items {
-                    if let AssemblyItem::Label(label) = item {
-                        if label.starts_with(".") {
-                            self.symbol_constituents
-                                .entry(current_label.clone())
-                                .or_default()
-                                .push(label.clone());
+        let mut current_label = String::new();
+        for (section, items) in &asm.sections {
+            let is_executable = asm
+                .section_attributes
+                .get(section)
+                .cloned()
+                .unwrap_or_else(|| SectionAttributes::default_for(section))
+                .exec;
+            // neither a routine nor a label's data continues into the next
+            // section
+            let mut current_routine: Option<usize> = None;
+            let mut data_label = None;
+            for item in items {
+                if let AssemblyItem::Mnemonic(instruction) = item {
+                    if let Some(routine) = current_routine {
+                        routine_bodies[routine].1.push(instruction);
+                    }
+                } else if let AssemblyItem::Data(layout) = item {
+                    if let Some(symbol) = data_label
+                        .and_then(|label| local_symbols.get_mut(label))
+                    {
+                        symbol.data.push(layout.clone());
+                    }
+                } else if let AssemblyItem::Label(label) = item {
+                    if label.starts_with(".") {
+                        constituents
+                            .entry(current_label.clone())
+                            .or_default()
+                            .push(label.clone());
+                    } else {
+                        current_label = label.clone();
+                        data_label = Some(label);
+                        current_routine = is_executable.then(|| {
+                            routine_bodies.push((label.clone(), Vec::new()));
+                            routine_bodies.len() - 1
+                        });
+                        let visibility = if asm.globals.contains(&current_label)
+                        {
+                            Visibility::Global
                         } else {
-                            current_label = label.clone();
-                            let visibility =
-                                if asm.globals.contains(&current_label) {
-                                    Visibility::Global
-                                } else {
-                                    Visibility::Private
-                                };
-                            local_symbols.insert(
-                                current_label.clone(),
-                                (visibility, Some(*section))
-                            );
+                            Visibility::Private
+                        };
+                        local_symbols.insert(
+                            current_label.clone(),
+                            Symbol {
+                                visibility,
+                                section: Some(section.clone()),
+                                data: Vec::new(),
+                                doc: asm.docs.get(label).cloned(),
+                                registers: is_executable
+                                    .then(RegisterUsage::default)
+                            }
+                        );
+                    }
+                }
+            }
+        }
+        (local_symbols, constituents, routine_bodies)
+    }
+
+    /// Adds the registers clobbered by callees to each routine's clobbers,
+    /// iterating until no routine changes. Calls to routines outside the
+    /// project are assumed to clobber all caller-saved registers.
+    fn propagate_clobbers(&mut self) {
+        loop {
+            let mut updates = Vec::new();
+            for (file, symbols) in &self.symbols {
+                for (name, symbol) in symbols {
+                    let Some(usage) = &symbol.registers else {
+                        continue;
+                    };
+                    let mut by_callees = Vec::new();
+                    for callee in &usage.calls {
+                        // a call to a conflicting global may reach any of
+                        // its definitions, and an extern is defined elsewhere
+                        let callees = match symbols.get(callee) {
+                            Some(local)
+                                if local.visibility != Visibility::External =>
+                            {
+                                vec![local]
+                            }
+                            _ => self
+                                .definitions(callee)
+                                .iter()
+                                .filter_map(|source| {
+                                    self.symbols.get(source)?.get(callee)
+                                })
+                                .collect()
+                        };
+                        let callee_usages = callees
+                            .iter()
+                            .map(|callee| callee.registers.as_ref())
+                            .collect::<Option<Vec<_>>>()
+                            .filter(|usages| !usages.is_empty());
+                        match callee_usages {
+                            Some(callee_usages) => {
+                                for callee_usage in callee_usages {
+                                    by_callees.extend(
+                                        callee_usage.clobbered.iter().cloned()
+                                    );
+                                }
+                            }
+                            None => {
+                                by_callees.extend(RegisterUsage::caller_saved())
+                            }
                         }
                     }
+                    let mut usage = usage.clone();
+                    if usage.clobber(&by_callees) {
+                        updates.push((file.clone(), name.clone(), usage));
+                    }
                 }
             }
+            if updates.is_empty() {
+                break;
+            }
+            for (file, name, usage) in updates {
+                let symbol = self
+                    .symbols
+                    .get_mut(&file)
+                    .unwrap()
+                    .get_mut(&name)
+                    .unwrap();
+                symbol.registers = Some(usage);
+            }
         }
-        self
     }
 
-    pub fn generate_docs(&self) -> Vec<(PathBuf, Docs)> {
+    /// The prose and tags of a documentation comment, followed by `warnings`,
+    /// as table cell lines.
+    fn describe(doc: Option<&DocComment>, warnings: Vec<Docs>) -> Docs {
+        let mut lines = doc
+            .iter()
+            .flat_map(|doc| &doc.text)
+            .cloned()
+            .collect::<Vec<_>>()
+            .split(|line| line.is_empty())
+            .filter(|paragraph| !paragraph.is_empty())
+            .map(|paragraph| Box::new(Docs::Text(paragraph.join(" "))))
+            .collect::<Vec<_>>();
+        for (tag, contents) in doc.iter().flat_map(|doc| &doc.tags) {
+            lines.push(Box::new(Docs::Concat(vec![
+                Box::new(Docs::InlineCode(format!("@{}", tag))),
+                Box::new(Docs::Text(format!(" {}", contents))),
+            ])));
+        }
+        lines.extend(warnings.into_iter().map(Box::new));
+        Docs::CellLines(lines)
+    }
+
+    /// Links to each of `files`, separated by commas.
+    fn file_links(files: &[PathBuf]) -> Docs {
+        let mut links = Vec::new();
+        for (i, file) in files.iter().enumerate() {
+            if i > 0 {
+                links.push(Box::new(Docs::Text(", ".into())));
+            }
+            links.push(Box::new(Docs::ResolveFile(file.clone())));
+        }
+        Docs::Concat(links)
+    }
+
+    /// The inferred clobbers of a routine and any disagreements with its
+    /// documentation, as table cell lines.
+    fn describe_registers(
+        usage: &RegisterUsage, doc: Option<&DocComment>
+    ) -> Docs {
+        let mut lines = Vec::new();
+        if !usage.clobbered.is_empty() {
+            let mut clobbers = vec![Box::new(Docs::Text("clobbers ".into()))];
+            for (i, register) in usage.clobbered.iter().enumerate() {
+                if i > 0 {
+                    clobbers.push(Box::new(Docs::Text(", ".into())));
+                }
+                clobbers.push(Box::new(Docs::InlineCode(register.to_string())));
+            }
+            lines.push(Box::new(Docs::Concat(clobbers)));
+        }
+        for mismatch in usage.mismatches(doc) {
+            lines.push(Box::new(Docs::Warning(Box::new(Docs::Text(
+                mismatch.to_string()
+            )))));
+        }
+        Docs::CellLines(lines)
+    }
+
+    pub fn generate_docs(
+        &self, layout: Option<&Layout>
+    ) -> Vec<(PathBuf, Docs)> {
         // what a nightmare!
         let mut docs = Vec::new();
         for (file, asm) in &self.files {
-            let mut symbol_docs = Vec::new();
-            for (symbol, (visibility, section)) in
-                self.symbols.get(file).unwrap()
-            {
-                let file = if *visibility == Visibility::External {
-                    self.internal_externs.get(symbol).cloned()
-                } else {
-                    None
-                };
+            let mut symbol_docs: LinkedHashMap<
+                Option<AssemblySection>,
+                Vec<_>
+            > = LinkedHashMap::new();
+            for (name, symbol) in self.symbols.get(file).unwrap() {
                 let constituents = self
                     .symbol_constituents
-                    .get(symbol)
+                    .get(file)
+                    .and_then(|constituents| constituents.get(name))
                     .map(|constituents| {
                         constituents
                             .iter()
                             .map(|constituent| {
-                                Box::new(Docs::InlineCode(format!(
-                                    "`{}`",
-                                    constituent
-                                )))
+                                Box::new(Docs::InlineCode(constituent.clone()))
                             })
                             .collect::<Vec<_>>()
                     })
                     .unwrap_or_default();
                 let mut symbol_cell =
-                    vec![Box::new(Docs::InlineCode(symbol.clone()))];
+                    vec![Box::new(Docs::InlineCode(name.clone()))];
                 for constituent in constituents {
                     symbol_cell.push(Box::new(Docs::Concat(vec![
                         Box::new(Docs::Text("- ".into())),
                         constituent,
                     ])));
                 }
-                symbol_docs.push(vec![
-                    Box::new(Docs::Text(visibility.to_string())),
+                let mut layout_cell = symbol
+                    .data
+                    .iter()
+                    .map(|layout| {
+                        Box::new(Docs::InlineCode(layout.source.clone()))
+                    })
+                    .collect::<Vec<_>>();
+                if let Some(size) = symbol.data_size() {
+                    if !symbol.data.is_empty() {
+                        layout_cell.push(Box::new(Docs::Text(format!(
+                            "{} byte{}",
+                            size,
+                            if size == 1 { "" } else { "s" }
+                        ))));
+                    }
+                }
+                let mut row = vec![
+                    Box::new(Docs::Text(symbol.visibility.to_string())),
                     Box::new(Docs::CellLines(symbol_cell)),
-                    Box::new(Docs::Text(
-                        section.map(|s| s.to_string()).unwrap_or_default()
-                    )),
-                    Box::new(if let Some(file) = file {
-                        Docs::ResolveFile(file)
-                    } else {
-                        Docs::Text("".into())
-                    }),
-                ]);
+                ];
+                if symbol.visibility == Visibility::External {
+                    row.push(Box::new(match self.extern_source(name) {
+                        ExternSource::Internal(files) => {
+                            Self::file_links(files)
+                        }
+                        ExternSource::Library(library) => {
+                            Docs::Text(library.to_string())
+                        }
+                        ExternSource::Unresolved => Docs::Warning(Box::new(
+                            Docs::Text("unresolved".into())
+                        ))
+                    }));
+                } else {
+                    let mut warnings = Vec::new();
+                    let definitions = self.definitions(name);
+                    if symbol.visibility == Visibility::Global
+                        && definitions.len() > 1
+                    {
+                        let others = definitions
+                            .iter()
+                            .filter(|other| *other != file)
+                            .cloned()
+                            .collect::<Vec<_>>();
+                        warnings.push(Docs::Warning(Box::new(Docs::Concat(
+                            vec![
+                                Box::new(Docs::Text("also defined in ".into())),
+                                Box::new(Self::file_links(&others)),
+                            ]
+                        ))));
+                    }
+                    row.push(Box::new(Self::describe(
+                        symbol.doc.as_ref(),
+                        warnings
+                    )));
+                    row.push(Box::new(match &symbol.registers {
+                        Some(usage) => {
+                            Self::describe_registers(usage, symbol.doc.as_ref())
+                        }
+                        None => Docs::CellLines(layout_cell)
+                    }));
+                    if let Some(layout) = layout {
+                        let placement =
+                            layout.placement(file, name, symbol.visibility);
+                        row.push(Box::new(match placement {
+                            Some(placement) => {
+                                Self::describe_placement(placement)
+                            }
+                            None => Docs::Text(String::new())
+                        }));
+                    }
+                }
+                symbol_docs
+                    .entry(symbol.section.clone())
+                    .or_default()
+                    .push(row);
+            }
+            let mut section_docs = symbol_docs
+                .into_iter()
+                .map(|(section, rows)| {
+                    let (title, extra_headers, attributes) = match &section {
+                        Some(section) => {
+                            let attributes = asm
+                                .section_attributes
+                                .get(section)
+                                .cloned()
+                                .unwrap_or_else(|| {
+                                    SectionAttributes::default_for(section)
+                                });
+                            (
+                                section.to_string(),
+                                [
+                                    "Description",
+                                    if attributes.exec {
+                                        "Registers"
+                                    } else {
+                                        "Layout"
+                                    }
+                                ]
+                                .into_iter()
+                                .chain(layout.map(|_| "Address"))
+                                .collect(),
+                                Some(Box::new(Docs::Text(
+                                    attributes.to_string()
+                                )))
+                            )
+                        }
+                        None => ("external".into(), vec!["Defined in"], None)
+                    };
+                    let table = Box::new(Docs::Table {
+                        header: ["Visibility", "Label"]
+                            .into_iter()
+                            .chain(extra_headers)
+                            .map(|header| Box::new(Docs::Text(header.into())))
+                            .collect(),
+                        rows
+                    });
+                    Box::new(Docs::Group {
+                        title,
+                        body: attributes.into_iter().chain([table]).collect()
+                    })
+                })
+                .collect::<Vec<_>>();
+            let undefined_rows = self
+                .undefined_globals(file)
+                .into_iter()
+                .map(|global| {
+                    vec![
+                        Box::new(Docs::Text(Visibility::Global.to_string())),
+                        Box::new(Docs::InlineCode(global.to_string())),
+                        Box::new(Self::describe(
+                            asm.docs.get(global),
+                            vec![Docs::Warning(Box::new(Docs::Text(
+                                "declared but never defined".into()
+                            )))]
+                        )),
+                    ]
+                })
+                .collect::<Vec<_>>();
+            if !undefined_rows.is_empty() {
+                section_docs.push(Box::new(Docs::Group {
+                    title: "undefined".into(),
+                    body: vec![Box::new(Docs::Table {
+                        header: ["Visibility", "Label", "Description"]
+                            .into_iter()
+                            .map(|header| Box::new(Docs::Text(header.into())))
+                            .collect(),
+                        rows: undefined_rows
+                    })]
+                }));
             }
             let defines_docs = asm
                 .defines
@@ -151,22 +779,208 @@ impl AssemblyProject {
                     })
                 })
                 .collect();
+            let struct_docs = asm
+                .structs
+                .iter()
+                .map(|struct_| {
+                    Box::new(Docs::Struct {
+                        name: struct_.name.clone(),
+                        size: struct_.size()
+                    })
+                })
+                .collect();
+            let include_docs = asm
+                .includes
+                .iter()
+                .map(|include| {
+                    Box::new(match self.resolve_include(file, include) {
+                        Some(include) => Docs::ResolveFile(include),
+                        None => Docs::InlineCode(include.display().to_string())
+                    })
+                })
+                .collect();
             let file_docs = Docs::File {
                 path: file.clone(),
-                symbols: Box::new(Docs::Table {
-                    header: vec![
-                        Box::new(Docs::Text("Visibility".into())),
-                        Box::new(Docs::Text("Label".into())),
-                        Box::new(Docs::Text("Section".into())),
-                        Box::new(Docs::Text("Defined in".into())),
-                    ],
-                    rows: symbol_docs
-                }),
+                includes: Box::new(Docs::List(include_docs)),
+                symbols: Box::new(Docs::Concat(section_docs)),
                 defines: Box::new(Docs::List(defines_docs)),
-                macros: Box::new(Docs::List(macro_docs))
+                macros: Box::new(Docs::List(macro_docs)),
+                structs: Box::new(Docs::List(struct_docs))
             };
             docs.push((file.clone(), file_docs));
         }
         docs
     }
+
+    /// The address and size of a linked symbol, for a table cell.
+    fn describe_placement(placement: Placement) -> Docs {
+        Docs::CellLines(vec![
+            Box::new(Docs::InlineCode(format!("{:#x}", placement.address))),
+            Box::new(Docs::Text(format!(
+                "{} byte{}",
+                placement.size,
+                if placement.size == 1 { "" } else { "s" }
+            ))),
+        ])
+    }
+
+    /// Every linked symbol of the project, largest first.
+    fn size_report(&self, layout: &Layout) -> Docs {
+        let mut placed = Vec::new();
+        for (file, symbols) in &self.symbols {
+            for (name, symbol) in symbols {
+                if let Some(placement) =
+                    layout.placement(file, name, symbol.visibility)
+                {
+                    placed.push((placement, name, file));
+                }
+            }
+        }
+        placed.sort_by(|(a, a_name, a_file), (b, b_name, b_file)| {
+            (b.size, a_name, a_file).cmp(&(a.size, b_name, b_file))
+        });
+        Docs::Table {
+            header: ["Label", "File", "Address", "Size"]
+                .into_iter()
+                .map(|header| Box::new(Docs::Text(header.into())))
+                .collect(),
+            rows: placed
+                .into_iter()
+                .map(|(placement, name, file)| {
+                    vec![
+                        Box::new(Docs::InlineCode(name.clone())),
+                        Box::new(Docs::ResolveFile(file.clone())),
+                        Box::new(Docs::InlineCode(format!(
+                            "{:#x}",
+                            placement.address
+                        ))),
+                        Box::new(Docs::Text(placement.size.to_string())),
+                    ]
+                })
+                .collect()
+        }
+    }
+
+    /// The front page, listing every file. `predefined` gives the macros
+    /// defined on the assembler command line, by name and value, and
+    /// `layout` adds a report of symbol sizes in the linked binary.
+    pub fn generate_index<'a>(
+        &self, title: &str,
+        predefined: impl IntoIterator<Item = (&'a String, &'a String)>,
+        layout: Option<&Layout>
+    ) -> Docs {
+        let files = self
+            .files
+            .keys()
+            .map(|file| {
+                Box::new(Docs::Concat(vec![
+                    Box::new(Docs::ResolveFile(file.clone())),
+                    Box::new(Docs::Text(" ".into())),
+                    Box::new(Docs::InlineCode(file.display().to_string())),
+                ]))
+            })
+            .collect();
+        let defines = predefined
+            .into_iter()
+            .map(|(name, value)| {
+                Box::new(Docs::Concat(vec![
+                    Box::new(Docs::Define { name: name.clone() }),
+                    Box::new(Docs::Text(" = ".into())),
+                    Box::new(Docs::InlineCode(value.clone())),
+                ]))
+            })
+            .collect();
+        let sizes = match layout {
+            Some(layout) => self.size_report(layout),
+            None => Docs::List(Vec::new())
+        };
+        Docs::Index {
+            title: title.to_string(),
+            files: Box::new(Docs::List(files)),
+            defines: Box::new(Docs::List(defines)),
+            sizes: Box::new(sizes)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::AssemblyProject;
+    use crate::{assembly_file::AssemblyFile, generator::Generator};
+
+    const HELPER: &str = "section .text
+global helper
+helper:
+    mov rcx, 1
+    ret
+";
+
+    const RUN: &str = "section .text
+extern helper
+global run
+run:
+    call helper
+    ret
+";
+
+    fn parse(sources: &[(&str, &str)]) -> Vec<(PathBuf, AssemblyFile)> {
+        let generator = Generator::new();
+        sources
+            .iter()
+            .map(|(file, source)| {
+                let file = PathBuf::from(file);
+                let asm = generator.parse_source(&file, source).unwrap();
+                (file, asm)
+            })
+            .collect()
+    }
+
+    /// What resolution computes for each file, in path order.
+    fn resolved(project: &AssemblyProject) -> String {
+        let mut summary = format!("{:?}\n", project.duplicate_globals());
+        for file in project.files().keys() {
+            summary += &format!(
+                "{}: {:?} {:?}\n",
+                file.display(),
+                project.symbols(file),
+                project.undefined_globals(file)
+            );
+        }
+        summary
+    }
+
+    #[test]
+    fn update_matches_full_rebuild() {
+        let mut project = AssemblyProject::build_from(parse(&[
+            ("a.nasm", HELPER),
+            ("b.nasm", RUN),
+            ("c.nasm", HELPER)
+        ]));
+        assert_eq!(project.definitions("helper").len(), 2);
+
+        // helper now clobbers rdx, which run inherits, and the duplicate
+        // definition goes away
+        let changed = HELPER.replace("rcx", "rdx");
+        let mut changes = parse(&[("a.nasm", &changed)])
+            .into_iter()
+            .map(|(file, asm)| (file, Some(asm)))
+            .collect::<Vec<_>>();
+        changes.push((PathBuf::from("c.nasm"), None));
+        project.update(changes);
+
+        let rebuilt = AssemblyProject::build_from(parse(&[
+            ("a.nasm", &changed),
+            ("b.nasm", RUN)
+        ]));
+        assert_eq!(resolved(&project), resolved(&rebuilt));
+        let clobbered = &project
+            .symbol(Path::new("b.nasm"), "run")
+            .and_then(|symbol| symbol.registers.as_ref())
+            .unwrap()
+            .clobbered;
+        assert_eq!(clobbered.len(), 1);
+        assert_eq!(clobbered[0].to_string(), "rdx");
+    }
 }
@@ -0,0 +1,207 @@
+// Copyright (C) 2024 Ethan Uppal. All rights reserved.
+
+//! An on-disk cache of parsed files, so unchanged files need not be lexed and
+//! parsed again.
+
+use std::{
+    fmt::Write,
+    fs, io,
+    path::{Path, PathBuf}
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{assembly_file::AssemblyFile, config::Dialect};
+
+/// A cached parse, along with what the included files were when it was made.
+#[derive(Serialize, Deserialize)]
+struct Entry<F> {
+    /// The resolution of each of `file.includes` and the digest of its
+    /// contents, or `None` if it could not be found.
+    includes: Vec<Option<(PathBuf, String)>>,
+    file: F
+}
+
+/// Incremented whenever the serialized form of parsed files changes, since
+/// entries are not self-describing.
+const FORMAT_VERSION: u32 = 1;
+
+/// Parsed files stored under a directory, keyed by a digest of the file's
+/// path and contents, the dialect, and the asmdoc and cache format versions. An
+/// entry is only used if every file it `%include`s still resolves to the same
+/// contents.
+pub struct ParseCache {
+    directory: PathBuf,
+    include_paths: Vec<PathBuf>
+}
+
+/// The SHA-256 digest of `parts`, in hexadecimal.
+fn digest<'a>(parts: impl IntoIterator<Item = &'a [u8]>) -> String {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update((part.len() as u64).to_le_bytes());
+        hasher.update(part);
+    }
+    hasher
+        .finalize()
+        .iter()
+        .fold(String::new(), |mut hex, byte| {
+            let _ = write!(hex, "{:02x}", byte);
+            hex
+        })
+}
+
+impl ParseCache {
+    /// A cache stored in `directory`, resolving `%include`s as
+    /// [`crate::assembly_project::AssemblyProject::resolve_include`] does.
+    pub fn new(directory: PathBuf, include_paths: Vec<PathBuf>) -> Self {
+        Self {
+            directory,
+            include_paths
+        }
+    }
+
+    fn entry_path(
+        &self, path: &Path, dialect: Dialect, source: &[u8]
+    ) -> PathBuf {
+        let key = digest([
+            env!("CARGO_PKG_VERSION").as_bytes(),
+            &FORMAT_VERSION.to_le_bytes(),
+            format!("{:?}", dialect).as_bytes(),
+            path.as_os_str().as_encoded_bytes(),
+            source
+        ]);
+        self.directory.join(key)
+    }
+
+    /// The file `include`, as written in `path`, refers to and the digest of
+    /// its contents.
+    fn resolve_include(
+        &self, path: &Path, include: &Path
+    ) -> Option<(PathBuf, String)> {
+        path.parent()
+            .into_iter()
+            .chain(self.include_paths.iter().map(PathBuf::as_path))
+            .map(|dir| dir.join(include))
+            .chain([include.to_path_buf()])
+            .find(|candidate| candidate.is_file())
+            .and_then(|candidate| {
+                let contents = fs::read(&candidate).ok()?;
+                Some((candidate, digest([contents.as_slice()])))
+            })
+    }
+
+    /// The cached parse of `source`, the contents of `path`, if there is one
+    /// and none of its includes changed. Unreadable entries are ignored.
+    pub fn get(
+        &self, path: &Path, dialect: Dialect, source: &[u8]
+    ) -> Option<AssemblyFile> {
+        let bytes = fs::read(self.entry_path(path, dialect, source)).ok()?;
+        let entry: Entry<AssemblyFile> = bincode::deserialize(&bytes).ok()?;
+        let unchanged = entry.includes.len() == entry.file.includes.len()
+            && entry.file.includes.iter().zip(&entry.includes).all(
+                |(include, recorded)| {
+                    self.resolve_include(path, include) == *recorded
+                }
+            );
+        unchanged.then_some(entry.file)
+    }
+
+    /// Stores `asm`, the parse of `source`, the contents of `path`.
+    pub fn put(
+        &self, path: &Path, dialect: Dialect, source: &[u8], asm: &AssemblyFile
+    ) -> io::Result<()> {
+        let entry = Entry {
+            includes: asm
+                .includes
+                .iter()
+                .map(|include| self.resolve_include(path, include))
+                .collect(),
+            file: asm
+        };
+        let bytes = bincode::serialize(&entry).map_err(io::Error::other)?;
+        fs::create_dir_all(&self.directory)?;
+        let entry_path = self.entry_path(path, dialect, source);
+        // write then rename so concurrent runs never see a partial entry
+        let partial = entry_path
+            .with_extension(format!("{}.partial", std::process::id()));
+        fs::write(&partial, bytes)?;
+        fs::rename(partial, entry_path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use super::ParseCache;
+    use crate::{assembly_file::AssemblyFile, config::Dialect, syntax::NASM};
+
+    /// Looks up `file` in `cache`, parsing and storing it on a miss. Returns
+    /// whether it was a hit.
+    fn lookup(cache: &ParseCache, file: &Path) -> bool {
+        let source = fs::read_to_string(file).unwrap();
+        if cache.get(file, Dialect::NASM, source.as_bytes()).is_some() {
+            return true;
+        }
+        let asm = AssemblyFile::parse::<NASM>(file, &source).unwrap();
+        cache
+            .put(file, Dialect::NASM, source.as_bytes(), &asm)
+            .unwrap();
+        false
+    }
+
+    #[test]
+    fn unchanged_files_are_hits() {
+        let root = tempfile::tempdir().unwrap();
+        let file = root.path().join("main.nasm");
+        fs::write(&file, "f:\n    ret\n").unwrap();
+        let cache = ParseCache::new(root.path().join("cache"), vec![]);
+
+        assert!(!lookup(&cache, &file));
+        assert!(lookup(&cache, &file));
+
+        fs::write(&file, "g:\n    ret\n").unwrap();
+        assert!(!lookup(&cache, &file));
+        assert!(lookup(&cache, &file));
+
+        let source = fs::read(&file).unwrap();
+        let asm = cache.get(&file, Dialect::NASM, &source).unwrap();
+        assert!(asm.lines.labels.contains_key("g"));
+        assert!(!asm.lines.labels.contains_key("f"));
+    }
+
+    #[test]
+    fn editing_an_include_invalidates_entries() {
+        let root = tempfile::tempdir().unwrap();
+        let include_directory = root.path().join("include");
+        fs::create_dir(&include_directory).unwrap();
+        let file = root.path().join("main.nasm");
+        let local = root.path().join("local.inc");
+        let shared = include_directory.join("shared.inc");
+        fs::write(
+            &file,
+            "%include \"local.inc\"\n%include \"shared.inc\"\nf:\n    ret\n"
+        )
+        .unwrap();
+        fs::write(&local, "%define A 1\n").unwrap();
+        fs::write(&shared, "%define B 1\n").unwrap();
+        let cache =
+            ParseCache::new(root.path().join("cache"), vec![include_directory]);
+
+        assert!(!lookup(&cache, &file));
+        assert!(lookup(&cache, &file));
+
+        fs::write(&local, "%define A 2\n").unwrap();
+        assert!(!lookup(&cache, &file));
+        assert!(lookup(&cache, &file));
+
+        fs::write(&shared, "%define B 2\n").unwrap();
+        assert!(!lookup(&cache, &file));
+
+        fs::remove_file(&shared).unwrap();
+        assert!(!lookup(&cache, &file));
+        assert!(lookup(&cache, &file));
+    }
+}
@@ -3,18 +3,141 @@
 use argh::FromArgs;
 use std::path::PathBuf;
 
+use crate::config::Format;
+
 /// Extracts smart documentation from an assembly project, given as a series of
 /// files and folders.
 #[derive(FromArgs)]
 pub struct CLI {
-    /// output directory for generated documentation
-    #[argh(
-        option,
-        short = 'o',
-        long = "output",
-        default = "PathBuf::from(\"docs\")"
-    )]
-    pub out_dir: PathBuf,
+    /// output directory for generated documentation (default: docs)
+    #[argh(option, short = 'o', long = "output")]
+    pub out_dir: Option<PathBuf>,
+
+    /// output format: markdown (the default), html, or ctags; may be repeated
+    #[argh(option)]
+    pub format: Vec<Format>,
+
+    /// project configuration file (default: asmdoc.toml, if present)
+    #[argh(option)]
+    pub config: Option<PathBuf>,
+
+    /// write every page directly into the output directory instead of
+    /// mirroring the input directory structure
+    #[argh(switch)]
+    pub flat: bool,
+
+    /// only document files matching this glob; may be repeated
+    #[argh(option)]
+    pub include: Vec<String>,
+
+    /// skip files matching this glob; may be repeated
+    #[argh(option)]
+    pub exclude: Vec<String>,
+
+    /// document files ignored by .gitignore
+    #[argh(switch)]
+    pub no_gitignore: bool,
+
+    /// descend into symbolically linked directories
+    #[argh(switch)]
+    pub follow_symlinks: bool,
+
+    /// cache parsed files in this directory, skipping unchanged files on
+    /// later runs
+    #[argh(option)]
+    pub cache_dir: Option<PathBuf>,
+
+    /// linked ELF file or linker map file whose symbol addresses and sizes
+    /// to document
+    #[argh(option)]
+    pub layout: Option<PathBuf>,
+
+    /// number of threads used to discover and parse files (default: one per
+    /// CPU)
+    #[argh(option, short = 'j')]
+    pub jobs: Option<usize>,
+
+    /// keep running, regenerating documentation whenever an input changes
+    #[argh(switch, short = 'w')]
+    pub watch: bool,
+
+    /// report documentation coverage instead of generating documentation
+    #[argh(switch)]
+    pub coverage: bool,
+
+    /// count private labels in the coverage report, not just globals
+    #[argh(switch)]
+    pub coverage_private: bool,
+
+    /// also write the coverage report as JSON to this file
+    #[argh(option)]
+    pub coverage_json: Option<PathBuf>,
+
+    /// fail if documentation coverage is below this percentage
+    #[argh(option)]
+    pub min_coverage: Option<f64>,
+
+    /// files or directories containing assembly code, replacing those in the
+    /// configuration file.
+    #[argh(positional)]
+    pub paths: Vec<PathBuf>,
+
+    #[argh(subcommand)]
+    pub command: Option<Command>
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand)]
+pub enum Command {
+    Check(Check),
+    Serve(Serve),
+    Lsp(Lsp),
+    Verify(Verify)
+}
+
+/// Reports documentation lints without generating documentation, exiting
+/// with an error if there are any.
+#[derive(FromArgs)]
+#[argh(subcommand, name = "check")]
+pub struct Check {
+    /// files or directories containing assembly code.
+    #[argh(positional)]
+    pub paths: Vec<PathBuf>
+}
+
+/// Generates HTML documentation and serves it on localhost, regenerating it
+/// and reloading open pages whenever an input changes.
+#[derive(FromArgs)]
+#[argh(subcommand, name = "serve")]
+pub struct Serve {
+    /// port to listen on
+    #[argh(option, short = 'p', default = "8000")]
+    pub port: u16,
+
+    /// files or directories containing assembly code.
+    #[argh(positional)]
+    pub paths: Vec<PathBuf>
+}
+
+/// Runs a language server over stdio, answering hover, go-to-definition,
+/// find-references, and symbol queries about the project.
+#[derive(FromArgs)]
+#[argh(subcommand, name = "lsp")]
+pub struct Lsp {
+    /// files or directories containing assembly code.
+    #[argh(positional)]
+    pub paths: Vec<PathBuf>
+}
+
+/// Compares the project with the symbol tables of assembled ELF objects or
+/// static archives, exiting with an error if they disagree.
+#[derive(FromArgs)]
+#[argh(subcommand, name = "verify")]
+pub struct Verify {
+    /// object file or static archive assembled from the project; may be
+    /// repeated
+    #[argh(option)]
+    pub object: Vec<PathBuf>,
 
     /// files or directories containing assembly code.
     #[argh(positional)]
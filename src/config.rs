@@ -0,0 +1,241 @@
+// Copyright (C) 2024 Ethan Uppal. All rights reserved.
+
+use std::{
+    error, fmt, fs, io,
+    path::{Path, PathBuf},
+    str::FromStr
+};
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use linked_hash_map::LinkedHashMap;
+use serde::Deserialize;
+
+use crate::library::Libraries;
+
+/// Name of the configuration file looked up in the project root.
+pub const CONFIG_FILE_NAME: &str = "asmdoc.toml";
+
+/// Project configuration, read from `asmdoc.toml`. Every field is optional;
+/// command-line flags take precedence over it.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// The directory containing the configuration file, against which globs
+    /// are matched.
+    #[serde(skip)]
+    pub root: PathBuf,
+    pub project: ProjectConfig,
+    pub input: InputConfig,
+    pub output: OutputConfig,
+    /// Symbols provided by libraries outside the project, by library name,
+    /// in addition to those of libc.
+    pub libraries: LinkedHashMap<String, Vec<String>>,
+    pub theme: ThemeConfig
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProjectConfig {
+    pub name: Option<String>,
+    pub version: Option<String>
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct InputConfig {
+    /// Files or directories containing assembly code.
+    pub paths: Vec<PathBuf>,
+    /// Globs of files to document. If empty, every file is.
+    pub include: Vec<String>,
+    /// Globs of files to leave out, even if included.
+    pub exclude: Vec<String>,
+    /// Whether to skip files ignored by `.gitignore` within a git repository
+    /// (default: true).
+    pub gitignore: Option<bool>,
+    /// Whether to descend into symbolically linked directories.
+    pub follow_symlinks: bool,
+    /// Directory in which to cache parsed files, if any.
+    pub cache_directory: Option<PathBuf>,
+    /// Linked ELF file or linker map file giving symbol addresses and sizes.
+    pub layout: Option<PathBuf>,
+    /// Directories searched for `%include`d files.
+    pub include_paths: Vec<PathBuf>,
+    /// Macros predefined on the assembler command line, by name.
+    pub defines: LinkedHashMap<String, String>,
+    /// The syntax of files matching each glob, checked in order. Files
+    /// matching none are assumed to be NASM.
+    pub dialects: LinkedHashMap<String, Dialect>
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputConfig {
+    pub directory: Option<PathBuf>,
+    pub formats: Vec<Format>,
+    /// See [`crate::cli::CLI::flat`].
+    pub flat: bool
+}
+
+/// The appearance of HTML pages. The Markdown backend has no theme.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ThemeConfig {
+    /// CSS font family of the text, e.g., `"serif"`.
+    pub font: Option<String>,
+    /// CSS color of headings and links, e.g., `"#06c"`.
+    pub accent: Option<String>,
+    /// Stylesheet whose rules follow the built-in ones.
+    pub stylesheet: Option<PathBuf>
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Dialect {
+    NASM
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    Markdown,
+    Html,
+    /// A `tags` file for vim, emacs, and other ctags-aware editors.
+    Ctags
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "markdown" => Ok(Self::Markdown),
+            "html" => Ok(Self::Html),
+            "ctags" => Ok(Self::Ctags),
+            _ => Err(format!("unknown format `{}`", s))
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, toml::de::Error),
+    InvalidGlob(String, globset::Error)
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(path, error) => {
+                write!(f, "Could not read {}: {}", path.display(), error)
+            }
+            Self::Parse(path, error) => {
+                write!(
+                    f,
+                    "Invalid configuration in {}: {}",
+                    path.display(),
+                    error
+                )
+            }
+            Self::InvalidGlob(glob, error) => {
+                write!(f, "Invalid glob `{}`: {}", glob, error)
+            }
+        }
+    }
+}
+
+impl error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::Io(_, error) => Some(error),
+            Self::Parse(_, error) => Some(error),
+            Self::InvalidGlob(_, error) => Some(error)
+        }
+    }
+}
+
+impl Config {
+    /// Reads the configuration at `path`. Relative paths in it are taken
+    /// relative to the directory containing `path`.
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let contents = fs::read_to_string(path)
+            .map_err(|error| ConfigError::Io(path.to_path_buf(), error))?;
+        let mut config: Self = toml::from_str(&contents)
+            .map_err(|error| ConfigError::Parse(path.to_path_buf(), error))?;
+
+        let root = path.parent().unwrap_or(Path::new(""));
+        config.root = root.to_path_buf();
+        let relative_to_root = |paths: &mut Vec<PathBuf>| {
+            for path in paths {
+                *path = root.join(&*path);
+            }
+        };
+        relative_to_root(&mut config.input.paths);
+        relative_to_root(&mut config.input.include_paths);
+        if let Some(directory) = &mut config.input.cache_directory {
+            *directory = root.join(&*directory);
+        }
+        if let Some(layout) = &mut config.input.layout {
+            *layout = root.join(&*layout);
+        }
+        if let Some(stylesheet) = &mut config.theme.stylesheet {
+            *stylesheet = root.join(&*stylesheet);
+        }
+        if let Some(directory) = &mut config.output.directory {
+            *directory = root.join(&*directory);
+        }
+        Ok(config)
+    }
+
+    /// Reads `asmdoc.toml` from `root` if it exists.
+    pub fn find(root: &Path) -> Result<Option<Self>, ConfigError> {
+        let path = root.join(CONFIG_FILE_NAME);
+        if path.is_file() {
+            Self::load(&path).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// The known external libraries.
+    pub fn libraries(&self) -> Libraries {
+        let mut libraries = Libraries::default();
+        for (library, symbols) in &self.libraries {
+            libraries.add(library, symbols.iter().cloned());
+        }
+        libraries
+    }
+}
+
+/// Compiles `globs` into a set. A glob without a `/` matches at any depth, as
+/// in `.gitignore`; others match paths relative to the project root.
+pub fn glob_set<S: AsRef<str>>(
+    globs: impl IntoIterator<Item = S>
+) -> Result<GlobSet, ConfigError> {
+    let mut builder = GlobSetBuilder::new();
+    for glob in globs {
+        let glob = glob.as_ref();
+        let glob = glob.trim_end_matches('/');
+        let pattern = if glob.contains('/') {
+            glob.trim_start_matches('/').to_string()
+        } else {
+            format!("**/{}", glob)
+        };
+        builder.add(
+            Glob::new(&pattern).map_err(|error| {
+                ConfigError::InvalidGlob(glob.into(), error)
+            })?
+        );
+    }
+    builder
+        .build()
+        .map_err(|error| ConfigError::InvalidGlob(String::new(), error))
+}
+
+/// Whether `path` or any directory containing it matches `set`, so that, as in
+/// `.gitignore`, a glob naming a directory covers everything in it.
+pub fn matches_within(set: &GlobSet, path: &Path) -> bool {
+    path.ancestors()
+        .take_while(|ancestor| !ancestor.as_os_str().is_empty())
+        .any(|ancestor| set.is_match(ancestor))
+}
@@ -0,0 +1,170 @@
+// Copyright (C) 2024 Ethan Uppal. All rights reserved.
+
+use std::{
+    fmt::{self, Display},
+    path::PathBuf
+};
+
+use serde::Serialize;
+
+use crate::{assembly_project::AssemblyProject, docs::Visibility};
+
+/// The kind of a documentable item.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ItemKind {
+    Symbol,
+    Macro,
+    Define,
+    Struct
+}
+
+impl Display for ItemKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ItemKind::Symbol => "symbol",
+            ItemKind::Macro => "macro",
+            ItemKind::Define => "define",
+            ItemKind::Struct => "struct"
+        }
+        .fmt(f)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Item {
+    pub kind: ItemKind,
+    pub name: String
+}
+
+/// Percentage of `total` items that are `documented`, where an empty set is
+/// fully documented.
+fn percentage(documented: usize, total: usize) -> f64 {
+    if total == 0 {
+        100.0
+    } else {
+        100.0 * documented as f64 / total as f64
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct FileCoverage {
+    pub path: PathBuf,
+    pub documented: usize,
+    pub total: usize,
+    pub percentage: f64,
+    pub undocumented: Vec<Item>
+}
+
+/// How much of a project carries documentation comments.
+#[derive(Debug, Serialize)]
+pub struct CoverageReport {
+    pub documented: usize,
+    pub total: usize,
+    pub percentage: f64,
+    pub files: Vec<FileCoverage>
+}
+
+impl CoverageReport {
+    /// Measures the coverage of the macros, defines, structures, and global
+    /// symbols of `project`, and also of its private symbols if
+    /// `include_private`.
+    pub fn of(project: &AssemblyProject, include_private: bool) -> Self {
+        let mut files = Vec::new();
+        for (path, asm) in project.files() {
+            let mut items = Vec::new();
+            for (name, symbol) in project.symbols(path).into_iter().flatten() {
+                let counted = match symbol.visibility {
+                    Visibility::Global => true,
+                    Visibility::Private => include_private,
+                    Visibility::External => false
+                };
+                if counted {
+                    items.push((ItemKind::Symbol, name, symbol.doc.is_some()));
+                }
+            }
+            let names = asm
+                .macros
+                .iter()
+                .map(|macro_| (ItemKind::Macro, &macro_.name))
+                .chain(asm.defines.iter().map(|name| (ItemKind::Define, name)))
+                .chain(
+                    asm.structs
+                        .iter()
+                        .map(|struct_| (ItemKind::Struct, &struct_.name))
+                );
+            for (kind, name) in names {
+                items.push((kind, name, asm.docs.contains_key(name)));
+            }
+
+            let documented = items.iter().filter(|item| item.2).count();
+            files.push(FileCoverage {
+                path: path.clone(),
+                documented,
+                total: items.len(),
+                percentage: percentage(documented, items.len()),
+                undocumented: items
+                    .into_iter()
+                    .filter(|item| !item.2)
+                    .map(|(kind, name, _)| Item {
+                        kind,
+                        name: name.clone()
+                    })
+                    .collect()
+            });
+        }
+
+        let documented = files.iter().map(|file| file.documented).sum();
+        let total = files.iter().map(|file| file.total).sum();
+        Self {
+            documented,
+            total,
+            percentage: percentage(documented, total),
+            files
+        }
+    }
+}
+
+impl Display for CoverageReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let rows = self
+            .files
+            .iter()
+            .map(|file| {
+                (
+                    file.path.display().to_string(),
+                    format!("{}/{}", file.documented, file.total),
+                    file.percentage
+                )
+            })
+            .chain([(
+                "total".to_string(),
+                format!("{}/{}", self.documented, self.total),
+                self.percentage
+            )])
+            .collect::<Vec<_>>();
+        let path_width = rows.iter().map(|row| row.0.len()).max().unwrap_or(0);
+        let count_width = rows.iter().map(|row| row.1.len()).max().unwrap_or(0);
+
+        for (i, (path, count, percentage)) in rows.iter().enumerate() {
+            if i == self.files.len() {
+                writeln!(f)?;
+            }
+            writeln!(
+                f,
+                "{:<path_width$}  {:>count_width$}  {:>5.1}%",
+                path, count, percentage
+            )?;
+            if let Some(file) = self.files.get(i) {
+                for item in &file.undocumented {
+                    writeln!(
+                        f,
+                        "  undocumented {} `{}`",
+                        item.kind, item.name
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
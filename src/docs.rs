@@ -4,13 +4,14 @@ use inform::fmt::IndentFormatter;
 use std::{
     collections::HashMap,
     fmt::{self, Display, Write},
+    io,
     marker::PhantomData,
-    path::PathBuf
+    path::{Component, Path, PathBuf}
 };
 
 const INDENT: usize = 2;
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum Visibility {
     Global,
     Private,
@@ -31,11 +32,26 @@ impl Display for Visibility {
 pub enum Docs {
     File {
         path: PathBuf,
+        includes: Box<Docs>,
         symbols: Box<Docs>,
         defines: Box<Docs>,
-        macros: Box<Docs>
+        macros: Box<Docs>,
+        structs: Box<Docs>
+    },
+    /// The front page of the documentation.
+    Index {
+        title: String,
+        files: Box<Docs>,
+        defines: Box<Docs>,
+        /// Sizes of symbols in the linked binary, if known.
+        sizes: Box<Docs>
     },
     Paragraphs(Vec<Box<Docs>>),
+    /// A titled subsection, e.g., the symbols of one assembly section.
+    Group {
+        title: String,
+        body: Vec<Box<Docs>>
+    },
     List(Vec<Box<Docs>>),
     Table {
         header: Vec<Box<Docs>>,
@@ -48,31 +64,86 @@ pub enum Docs {
     Define {
         name: String
     },
+    Struct {
+        name: String,
+        size: Option<usize>
+    },
     InlineCode(String),
     Text(String),
     CellLines(Vec<Box<Docs>>),
     ResolveFile(PathBuf),
-    Concat(Vec<Box<Docs>>)
+    Concat(Vec<Box<Docs>>),
+    /// A problem found in the code, such as a conflicting definition.
+    Warning(Box<Docs>)
+}
+
+/// Resolves links between pages of documentation.
+pub struct Links<'docs> {
+    file_map: &'docs HashMap<PathBuf, PathBuf>,
+    /// The location of the page being written, if known.
+    page: Option<&'docs Path>,
+    /// CSS added after the built-in stylesheet, for backends with one.
+    style: &'docs str
+}
+
+impl<'docs> Links<'docs> {
+    /// The path to the documentation for `file`, relative to the page being
+    /// written.
+    pub fn resolve(&self, file: &Path) -> Option<PathBuf> {
+        let target = self.file_map.get(file)?;
+        let Some(page_dir) = self.page.and_then(Path::parent) else {
+            return Some(target.clone());
+        };
+
+        Some(relative_path(page_dir, target))
+    }
+}
+
+/// `to` relative to the directory `from_dir`, falling back to `to` itself
+/// when it is absolute and they share no common ancestor. Both should be
+/// absolute or both relative to the same directory.
+pub fn relative_path(from_dir: &Path, to: &Path) -> PathBuf {
+    fn normal(path: &Path) -> Vec<Component<'_>> {
+        path.components()
+            .filter(|component| *component != Component::CurDir)
+            .collect()
+    }
+    let from = normal(from_dir);
+    let to_components = normal(to);
+    let common = from
+        .iter()
+        .zip(&to_components)
+        .take_while(|(from, to)| from == to)
+        .count();
+    if common == 0 && to.has_root() {
+        return to.to_path_buf();
+    }
+    let mut relative = PathBuf::new();
+    for _ in common..from.len() {
+        relative.push("..");
+    }
+    relative.extend(&to_components[common..]);
+    relative
 }
 
 pub trait Backend {
-    fn fmt(
-        docs: &Docs, f: &mut IndentFormatter,
-        file_map: &HashMap<PathBuf, PathBuf>
-    ) -> fmt::Result;
+    /// The file extension of pages, e.g., `md`.
+    const EXTENSION: &'static str;
+
+    fn fmt(docs: &Docs, f: &mut IndentFormatter, links: &Links) -> fmt::Result;
 }
 
 struct IndentDisplay<'docs, B: Backend>(
     PhantomData<B>,
     &'docs Docs,
-    &'docs HashMap<PathBuf, PathBuf>
+    Links<'docs>
 );
 
 impl<'docs, B: Backend> Display for IndentDisplay<'docs, B> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let mut f = IndentFormatter::new(f, INDENT);
-        let Self(_, docs, file_map) = self;
-        B::fmt(docs, &mut f, file_map)
+        let Self(_, docs, links) = self;
+        B::fmt(docs, &mut f, links)
     }
 }
 
@@ -81,26 +152,61 @@ impl Docs {
     /// a file path to the intended location of the documentation for that
     /// file. For example, if a file references `foo.nasm`, then you must supply
     /// the path (e.g., `foo.md`) where the documentation for `foo.nasm`
-    /// will be supplied.
+    /// will be supplied. All paths must be relative to the same output
+    /// directory; links are written relative to the page itself. `style` is
+    /// CSS added to the built-in stylesheet of HTML pages.
     pub fn to<B: Backend>(
-        &self, file_map: &HashMap<PathBuf, PathBuf>
+        &self, file_map: &HashMap<PathBuf, PathBuf>, style: &str
     ) -> String {
-        IndentDisplay::<B>(PhantomData, self, file_map).to_string()
+        self.display::<B>(file_map, style).to_string()
+    }
+
+    /// Renders this documentation into `sink` as it goes, without building
+    /// the page in memory. See [`Docs::to`] for the meaning of `file_map`
+    /// and `style`. `sink` should be buffered.
+    pub fn write_to<B: Backend>(
+        &self, file_map: &HashMap<PathBuf, PathBuf>, style: &str,
+        sink: &mut impl io::Write
+    ) -> io::Result<()> {
+        write!(sink, "{}", self.display::<B>(file_map, style))
+    }
+
+    fn display<'docs, B: Backend>(
+        &'docs self, file_map: &'docs HashMap<PathBuf, PathBuf>,
+        style: &'docs str
+    ) -> IndentDisplay<'docs, B> {
+        let page = match self {
+            Self::File { path, .. } => file_map.get(path).map(PathBuf::as_path),
+            _ => None
+        };
+        IndentDisplay(
+            PhantomData,
+            self,
+            Links {
+                file_map,
+                page,
+                style
+            }
+        )
     }
 
     pub fn is_empty(&self) -> bool {
         match self {
             Self::File { .. } => false,
+            Self::Index { .. } => false,
             Self::Paragraphs(items) => items.is_empty(),
+            Self::Group { body, .. } => body.iter().all(|item| item.is_empty()),
             Self::List(list) => list.is_empty(),
             Self::Table { rows, .. } => rows.is_empty(),
             Self::Macro { .. } => false,
             Self::Define { .. } => false,
+            Self::Struct { .. } => false,
             Self::InlineCode(..) => false,
             Self::Text(..) => false,
             Self::CellLines(lines) => lines.is_empty(),
             Self::ResolveFile(..) => false,
-            Self::Concat(items) => items.is_empty()
+            Self::Concat(items) => items.is_empty(),
+            Self::Warning(..) => false
         }
     }
 }
@@ -108,16 +214,17 @@ impl Docs {
 pub struct Markdown;
 
 impl Backend for Markdown {
-    fn fmt(
-        docs: &Docs, f: &mut IndentFormatter,
-        file_map: &HashMap<PathBuf, PathBuf>
-    ) -> fmt::Result {
+    const EXTENSION: &'static str = "md";
+
+    fn fmt(docs: &Docs, f: &mut IndentFormatter, links: &Links) -> fmt::Result {
         match docs {
             Docs::File {
                 path,
+                includes,
                 symbols,
                 defines,
-                macros
+                macros,
+                structs
             } => {
                 writeln!(f, "<!-- This file was generated by asmdoc <https://github.com/ethanuppal/asmdoc>. -->")?;
                 writeln!(
@@ -126,21 +233,62 @@ impl Backend for Markdown {
                     path.file_name().unwrap().to_string_lossy()
                 )?;
 
+                if !includes.is_empty() {
+                    writeln!(f, "## Includes")?;
+                    Self::fmt(includes, f, links)?;
+                    writeln!(f)?;
+                }
+
                 if !symbols.is_empty() {
                     writeln!(f, "## Symbols")?;
-                    Self::fmt(symbols, f, file_map)?;
+                    Self::fmt(symbols, f, links)?;
                     writeln!(f)?;
                 }
 
                 if !defines.is_empty() {
                     writeln!(f, "## Defines")?;
-                    Self::fmt(defines, f, file_map)?;
+                    Self::fmt(defines, f, links)?;
                     writeln!(f)?;
                 }
 
                 if !macros.is_empty() {
                     writeln!(f, "## Macros")?;
-                    Self::fmt(macros, f, file_map)?;
+                    Self::fmt(macros, f, links)?;
+                    writeln!(f)?;
+                }
+
+                if !structs.is_empty() {
+                    writeln!(f, "## Structures")?;
+                    Self::fmt(structs, f, links)?;
+                    writeln!(f)?;
+                }
+
+                Ok(())
+            }
+            Docs::Index {
+                title,
+                files,
+                defines,
+                sizes
+            } => {
+                writeln!(f, "<!-- This file was generated by asmdoc <https://github.com/ethanuppal/asmdoc>. -->")?;
+                writeln!(f, "# {}\n", title)?;
+
+                if !files.is_empty() {
+                    writeln!(f, "## Files")?;
+                    Self::fmt(files, f, links)?;
+                    writeln!(f)?;
+                }
+
+                if !defines.is_empty() {
+                    writeln!(f, "## Predefined macros")?;
+                    Self::fmt(defines, f, links)?;
+                    writeln!(f)?;
+                }
+
+                if !sizes.is_empty() {
+                    writeln!(f, "## Sizes")?;
+                    Self::fmt(sizes, f, links)?;
                     writeln!(f)?;
                 }
 
@@ -148,18 +296,24 @@ impl Backend for Markdown {
             }
             Docs::Paragraphs(items) => items.iter().try_for_each(|item| {
                 write!(f, "- ")
-                    .and_then(|_| Self::fmt(item, f, file_map))
+                    .and_then(|_| Self::fmt(item, f, links))
                     .and_then(|_| write!(f, "\n\n"))
             }),
+            Docs::Group { title, body } => {
+                write!(f, "\n### {}", title)?;
+                body.iter().try_for_each(|item| {
+                    writeln!(f).and_then(|_| Self::fmt(item, f, links))
+                })
+            }
             Docs::List(items) => items.iter().try_for_each(|item| {
                 write!(f, "- ")
-                    .and_then(|_| Self::fmt(item, f, file_map))
+                    .and_then(|_| Self::fmt(item, f, links))
                     .and_then(|_| writeln!(f))
             }),
             Docs::Table { header, rows } => {
                 write!(f, "\n| ")?;
                 for col in header {
-                    Self::fmt(col, f, file_map)?;
+                    Self::fmt(col, f, links)?;
                     write!(f, " |")?;
                 }
                 writeln!(f)?;
@@ -173,7 +327,7 @@ impl Backend for Markdown {
                 for row in rows {
                     write!(f, "| ")?;
                     for col in row {
-                        Self::fmt(col, f, file_map)?;
+                        Self::fmt(col, f, links)?;
                         write!(f, " |")?;
                     }
                     writeln!(f)?;
@@ -191,14 +345,33 @@ impl Backend for Markdown {
                 )
             }
             Docs::Define { name } => write!(f, "`{}`", name),
-            Docs::InlineCode(code) => write!(f, "`{}`", code),
+            Docs::Struct { name, size } => {
+                write!(f, "`{}`", name)?;
+                if let Some(size) = size {
+                    write!(
+                        f,
+                        " ({} byte{})",
+                        size,
+                        if *size == 1 { "" } else { "s" }
+                    )?;
+                }
+                Ok(())
+            }
+            Docs::InlineCode(code) => {
+                // the fence must be longer than any run of backticks inside
+                let longest_run =
+                    code.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+                let fence = "`".repeat(longest_run + 1);
+                let padding = if longest_run > 0 { " " } else { "" };
+                write!(f, "{}{}{}{}{}", fence, padding, code, padding, fence)
+            }
             Docs::Text(text) => write!(f, "{}", text),
             Docs::CellLines(lines) => {
                 for (i, line) in lines.iter().enumerate() {
                     if i > 0 {
                         write!(f, "<br>")?;
                     }
-                    Self::fmt(line, f, file_map)?;
+                    Self::fmt(line, f, links)?;
                 }
                 Ok(())
             }
@@ -207,12 +380,256 @@ impl Backend for Markdown {
                     f,
                     "[{}]({})",
                     file.file_name().unwrap().to_string_lossy(),
-                    file_map.get(file).unwrap().to_string_lossy()
+                    links.resolve(file).unwrap().to_string_lossy()
+                )
+            }
+            Docs::Concat(items) => {
+                items.iter().try_for_each(|item| Self::fmt(item, f, links))
+            }
+            Docs::Warning(message) => {
+                write!(f, "**warning:** ")?;
+                Self::fmt(message, f, links)
+            }
+        }
+    }
+}
+
+pub struct Html;
+
+/// Stylesheet embedded in every HTML page.
+const HTML_STYLE: &str = "body { font-family: sans-serif; max-width: 60em; \
+margin: 2em auto; padding: 0 1em; line-height: 1.5 } table { \
+border-collapse: collapse } th, td { border: 1px solid #ccc; padding: 0.3em \
+0.6em; text-align: left; vertical-align: top } code { background: #f4f4f4; \
+padding: 0 0.2em } .warning { color: #b00 }";
+
+/// Escapes `text` for use in HTML text and attribute values.
+struct Escaped<'a>(&'a str);
+
+impl Display for Escaped<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for c in self.0.chars() {
+            match c {
+                '&' => write!(f, "&amp;")?,
+                '<' => write!(f, "&lt;")?,
+                '>' => write!(f, "&gt;")?,
+                '"' => write!(f, "&quot;")?,
+                '\'' => write!(f, "&#39;")?,
+                c => write!(f, "{}", c)?
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Html {
+    fn page_start(
+        f: &mut IndentFormatter, title: &str, links: &Links
+    ) -> fmt::Result {
+        writeln!(f, "<!DOCTYPE html>")?;
+        writeln!(f, "<!-- This file was generated by asmdoc <https://github.com/ethanuppal/asmdoc>. -->")?;
+        writeln!(f, "<html>")?;
+        writeln!(f, "<head>")?;
+        writeln!(f, "<meta charset=\"utf-8\">")?;
+        writeln!(f, "<title>{}</title>", Escaped(title))?;
+        if links.style.is_empty() {
+            writeln!(f, "<style>{}</style>", HTML_STYLE)?;
+        } else {
+            writeln!(f, "<style>{} {}</style>", HTML_STYLE, links.style)?;
+        }
+        writeln!(f, "</head>")?;
+        writeln!(f, "<body>")?;
+        writeln!(f, "<h1>{}</h1>", Escaped(title))
+    }
+
+    fn page_end(f: &mut IndentFormatter) -> fmt::Result {
+        writeln!(f, "</body>")?;
+        writeln!(f, "</html>")
+    }
+
+    /// Writes `docs` under a second-level heading unless it is empty.
+    fn section(
+        f: &mut IndentFormatter, title: &str, docs: &Docs, links: &Links
+    ) -> fmt::Result {
+        if !docs.is_empty() {
+            writeln!(f, "<h2>{}</h2>", title)?;
+            Self::fmt(docs, f, links)?;
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl Backend for Html {
+    const EXTENSION: &'static str = "html";
+
+    fn fmt(docs: &Docs, f: &mut IndentFormatter, links: &Links) -> fmt::Result {
+        match docs {
+            Docs::File {
+                path,
+                includes,
+                symbols,
+                defines,
+                macros,
+                structs
+            } => {
+                Self::page_start(
+                    f,
+                    &path.file_name().unwrap().to_string_lossy(),
+                    links
+                )?;
+                Self::section(f, "Includes", includes, links)?;
+                Self::section(f, "Symbols", symbols, links)?;
+                Self::section(f, "Defines", defines, links)?;
+                Self::section(f, "Macros", macros, links)?;
+                Self::section(f, "Structures", structs, links)?;
+                Self::page_end(f)
+            }
+            Docs::Index {
+                title,
+                files,
+                defines,
+                sizes
+            } => {
+                Self::page_start(f, title, links)?;
+                Self::section(f, "Files", files, links)?;
+                Self::section(f, "Predefined macros", defines, links)?;
+                Self::section(f, "Sizes", sizes, links)?;
+                Self::page_end(f)
+            }
+            Docs::Paragraphs(items) => items.iter().try_for_each(|item| {
+                write!(f, "<p>")
+                    .and_then(|_| Self::fmt(item, f, links))
+                    .and_then(|_| writeln!(f, "</p>"))
+            }),
+            Docs::Group { title, body } => {
+                writeln!(f, "<h3>{}</h3>", Escaped(title))?;
+                body.iter().try_for_each(|item| {
+                    Self::fmt(item, f, links).and_then(|_| writeln!(f))
+                })
+            }
+            Docs::List(items) => {
+                writeln!(f, "<ul>")?;
+                for item in items {
+                    write!(f, "<li>")?;
+                    Self::fmt(item, f, links)?;
+                    writeln!(f, "</li>")?;
+                }
+                write!(f, "</ul>")
+            }
+            Docs::Table { header, rows } => {
+                writeln!(f, "<table>")?;
+                write!(f, "<tr>")?;
+                for col in header {
+                    write!(f, "<th>")?;
+                    Self::fmt(col, f, links)?;
+                    write!(f, "</th>")?;
+                }
+                writeln!(f, "</tr>")?;
+                for row in rows {
+                    write!(f, "<tr>")?;
+                    for col in row {
+                        write!(f, "<td>")?;
+                        Self::fmt(col, f, links)?;
+                        write!(f, "</td>")?;
+                    }
+                    writeln!(f, "</tr>")?;
+                }
+                write!(f, "</table>")
+            }
+            Docs::Macro { name, arg_count } => {
+                write!(
+                    f,
+                    "<code>{}</code> ({} argument{})",
+                    Escaped(name),
+                    arg_count,
+                    if *arg_count == 1 { "" } else { "s" }
                 )
             }
-            Docs::Concat(items) => items
-                .iter()
-                .try_for_each(|item| Self::fmt(item, f, file_map))
+            Docs::Define { name } => {
+                write!(f, "<code>{}</code>", Escaped(name))
+            }
+            Docs::Struct { name, size } => {
+                write!(f, "<code>{}</code>", Escaped(name))?;
+                if let Some(size) = size {
+                    write!(
+                        f,
+                        " ({} byte{})",
+                        size,
+                        if *size == 1 { "" } else { "s" }
+                    )?;
+                }
+                Ok(())
+            }
+            Docs::InlineCode(code) => {
+                write!(f, "<code>{}</code>", Escaped(code))
+            }
+            Docs::Text(text) => write!(f, "{}", Escaped(text)),
+            Docs::CellLines(lines) => {
+                for (i, line) in lines.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "<br>")?;
+                    }
+                    Self::fmt(line, f, links)?;
+                }
+                Ok(())
+            }
+            Docs::ResolveFile(file) => {
+                write!(
+                    f,
+                    "<a href=\"{}\">{}</a>",
+                    Escaped(&links.resolve(file).unwrap().to_string_lossy()),
+                    Escaped(&file.file_name().unwrap().to_string_lossy())
+                )
+            }
+            Docs::Concat(items) => {
+                items.iter().try_for_each(|item| Self::fmt(item, f, links))
+            }
+            Docs::Warning(message) => {
+                write!(
+                    f,
+                    "<span class=\"warning\"><strong>warning:</strong> "
+                )?;
+                Self::fmt(message, f, links)?;
+                write!(f, "</span>")
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        path::{Path, PathBuf}
+    };
+
+    use super::{relative_path, Docs, Html, Markdown};
+
+    #[test]
+    fn style_follows_built_in_stylesheet() {
+        let index = Docs::Index {
+            title: "Docs".into(),
+            files: Box::new(Docs::List(Vec::new())),
+            defines: Box::new(Docs::List(Vec::new())),
+            sizes: Box::new(Docs::List(Vec::new()))
+        };
+        let style = "body { font-family: serif }";
+        let html = index.to::<Html>(&HashMap::new(), style);
+        let line = html.lines().find(|line| line.starts_with("<style>"));
+        assert!(line
+            .unwrap()
+            .ends_with(" body { font-family: serif }</style>"));
+        assert!(!index.to::<Markdown>(&HashMap::new(), style).contains(style));
+    }
+
+    #[test]
+    fn relative_paths_climb_to_common_ancestor() {
+        let relative = |from, to| relative_path(Path::new(from), Path::new(to));
+        assert_eq!(relative("a/b", "a/c/d.md"), PathBuf::from("../c/d.md"));
+        assert_eq!(relative("./a", "a/d.md"), PathBuf::from("d.md"));
+        assert_eq!(relative("", "a/d.md"), PathBuf::from("a/d.md"));
+        assert_eq!(relative("/x/y", "/x/z.nasm"), PathBuf::from("../z.nasm"));
+        assert_eq!(relative("x/y", "/z.nasm"), PathBuf::from("/z.nasm"));
+    }
+}
@@ -0,0 +1,973 @@
+// Copyright (C) 2024 Ethan Uppal. All rights reserved.
+
+//! The whole pipeline, from finding assembly files to writing their
+//! documentation, for embedding asmdoc in other programs:
+//!
+//! ```no_run
+//! use asmdoc::{config::Format, generator::Generator};
+//!
+//! let generated = Generator::new()
+//!     .input("src")
+//!     .output("target/asmdoc")
+//!     .backend(Format::Html)
+//!     .run()?;
+//! println!("wrote {} pages", generated.pages.written);
+//! # Ok::<(), asmdoc::generator::GenerateError>(())
+//! ```
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    error, ffi,
+    fmt::{self, Display},
+    fs::{self, File},
+    io::{self, BufRead, BufReader, BufWriter, Read},
+    path::{self, Component, Path, PathBuf},
+    process,
+    sync::Mutex
+};
+
+use globset::GlobSet;
+use ignore::{WalkBuilder, WalkState};
+use linked_hash_map::LinkedHashMap;
+use rayon::{
+    iter::{IntoParallelIterator, ParallelIterator},
+    ThreadPoolBuildError, ThreadPoolBuilder
+};
+
+use crate::{
+    assembly_file::AssemblyFile,
+    assembly_project::{normalize, AssemblyProject},
+    cache::ParseCache,
+    config::{glob_set, matches_within, Config, ConfigError, Dialect, Format},
+    docs::{Backend, Docs, Html, Markdown},
+    layout::{Layout, LayoutError},
+    library::Libraries,
+    syntax::{self, nasm::NASMParseError},
+    tags
+};
+
+#[derive(Debug)]
+pub enum GenerateError {
+    Config(ConfigError),
+    Read(PathBuf, io::Error),
+    Write(PathBuf, io::Error),
+    InvalidUtf8(PathBuf),
+    Parse(PathBuf, NASMParseError),
+    /// The output directory exists but is not a directory.
+    NotADirectory(PathBuf),
+    ThreadPool(ThreadPoolBuildError),
+    Layout(LayoutError),
+    /// An input directory could not be walked.
+    Walk(ignore::Error)
+}
+
+impl Display for GenerateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Config(error) => error.fmt(f),
+            Self::Read(path, error) => {
+                write!(f, "Could not read {}: {}", path.display(), error)
+            }
+            Self::Write(path, error) => {
+                write!(f, "Could not write {}: {}", path.display(), error)
+            }
+            Self::InvalidUtf8(path) => {
+                write!(f, "{} is not valid UTF-8", path.display())
+            }
+            Self::Parse(path, error) => {
+                write!(f, "Could not parse {}: {}", path.display(), error)
+            }
+            Self::NotADirectory(path) => {
+                write!(f, "Output {} is not a directory", path.display())
+            }
+            Self::ThreadPool(error) => {
+                write!(f, "Could not start worker threads: {}", error)
+            }
+            Self::Layout(error) => error.fmt(f),
+            Self::Walk(error) => {
+                write!(f, "Could not list input files: {}", error)
+            }
+        }
+    }
+}
+
+impl error::Error for GenerateError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            // displayed as the wrapped error itself
+            Self::Config(error) => error.source(),
+            Self::Layout(error) => error.source(),
+            Self::Read(_, error) | Self::Write(_, error) => Some(error),
+            Self::Parse(_, error) => Some(error),
+            Self::ThreadPool(error) => Some(error),
+            Self::Walk(error) => Some(error),
+            Self::InvalidUtf8(_) | Self::NotADirectory(_) => None
+        }
+    }
+}
+
+impl From<ConfigError> for GenerateError {
+    fn from(error: ConfigError) -> Self {
+        Self::Config(error)
+    }
+}
+
+/// The pages written by [`Generator::write`].
+#[derive(Debug, Default)]
+pub struct Pages {
+    /// Every page, relative to the output directory.
+    pub paths: HashSet<PathBuf>,
+    /// How many pages changed.
+    pub written: usize
+}
+
+/// The result of [`Generator::run`].
+pub struct Generated {
+    pub project: AssemblyProject,
+    pub pages: Pages
+}
+
+/// Generates documentation for an assembly project. Each step of
+/// [`Generator::run`] is also available separately, e.g., to regenerate after
+/// a file changes.
+pub struct Generator {
+    /// The directory against which globs are matched.
+    root: PathBuf,
+    inputs: Vec<PathBuf>,
+    output: PathBuf,
+    formats: Vec<Format>,
+    flat: bool,
+    title: String,
+    include: Vec<String>,
+    exclude: Vec<String>,
+    gitignore: bool,
+    follow_symlinks: bool,
+    syntax: Dialect,
+    dialects: LinkedHashMap<String, Dialect>,
+    include_paths: Vec<PathBuf>,
+    defines: LinkedHashMap<String, String>,
+    libraries: Libraries,
+    cache: Option<PathBuf>,
+    layout: Option<PathBuf>,
+    /// CSS added to every HTML page.
+    style: String,
+    stylesheet: Option<PathBuf>,
+    jobs: usize
+}
+
+/// Compiled globs for deciding which files to document and how.
+struct Matchers {
+    include: GlobSet,
+    exclude: GlobSet,
+    dialects: GlobSet
+}
+
+impl Default for Generator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Generator {
+    /// A generator writing Markdown to `docs`, with no inputs yet.
+    pub fn new() -> Self {
+        Self {
+            root: PathBuf::new(),
+            inputs: Vec::new(),
+            output: PathBuf::from("docs"),
+            formats: Vec::new(),
+            flat: false,
+            title: "Documentation".into(),
+            include: Vec::new(),
+            exclude: Vec::new(),
+            gitignore: true,
+            follow_symlinks: false,
+            syntax: Dialect::NASM,
+            dialects: LinkedHashMap::new(),
+            include_paths: Vec::new(),
+            defines: LinkedHashMap::new(),
+            libraries: Libraries::default(),
+            cache: None,
+            layout: None,
+            style: String::new(),
+            stylesheet: None,
+            jobs: 0
+        }
+    }
+
+    /// A generator configured by `config`, which builder methods can then
+    /// override.
+    pub fn from_config(config: &Config) -> Self {
+        let mut generator = Self::new()
+            .root(&config.root)
+            .flat(config.output.flat)
+            .gitignore(config.input.gitignore.unwrap_or(true))
+            .follow_symlinks(config.input.follow_symlinks);
+        generator.inputs = config.input.paths.clone();
+        if let Some(directory) = &config.output.directory {
+            generator.output = directory.clone();
+        }
+        generator.formats = config.output.formats.clone();
+        generator.title = match (&config.project.name, &config.project.version)
+        {
+            (Some(name), Some(version)) => format!("{} {}", name, version),
+            (Some(name), None) => name.clone(),
+            _ => generator.title
+        };
+        generator.include = config.input.include.clone();
+        generator.exclude = config.input.exclude.clone();
+        generator.dialects = config.input.dialects.clone();
+        generator.include_paths = config.input.include_paths.clone();
+        generator.defines = config.input.defines.clone();
+        generator.libraries = config.libraries();
+        generator.cache = config.input.cache_directory.clone();
+        generator.layout = config.input.layout.clone();
+        if let Some(font) = &config.theme.font {
+            generator =
+                generator.style(format!("body {{ font-family: {} }}", font));
+        }
+        if let Some(accent) = &config.theme.accent {
+            generator = generator
+                .style(format!("h1, h2, h3, a {{ color: {} }}", accent));
+        }
+        generator.stylesheet = config.theme.stylesheet.clone();
+        generator
+    }
+
+    /// Matches globs against paths relative to `root` (default: the current
+    /// directory).
+    pub fn root(mut self, root: impl Into<PathBuf>) -> Self {
+        self.root = root.into();
+        self
+    }
+
+    /// Adds a file or directory of assembly code to document.
+    pub fn input(mut self, path: impl Into<PathBuf>) -> Self {
+        self.inputs.push(path.into());
+        self
+    }
+
+    /// Replaces the files and directories to document.
+    pub fn inputs(
+        mut self, paths: impl IntoIterator<Item = impl Into<PathBuf>>
+    ) -> Self {
+        self.inputs = paths.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the output directory (default: `docs`).
+    pub fn output(mut self, directory: impl Into<PathBuf>) -> Self {
+        self.output = directory.into();
+        self
+    }
+
+    /// Adds an output format. Markdown is written if none is added.
+    pub fn backend(mut self, format: Format) -> Self {
+        if !self.formats.contains(&format) {
+            self.formats.push(format);
+        }
+        self
+    }
+
+    /// See [`crate::cli::CLI::flat`].
+    pub fn flat(mut self, flat: bool) -> Self {
+        self.flat = flat;
+        self
+    }
+
+    /// Sets the title of the front page.
+    pub fn title(mut self, title: impl Into<String>) -> Self {
+        self.title = title.into();
+        self
+    }
+
+    /// Only documents files matching `glob`, along with those matching other
+    /// included globs. Globs follow `.gitignore` conventions.
+    pub fn include(mut self, glob: impl Into<String>) -> Self {
+        self.include.push(glob.into());
+        self
+    }
+
+    /// Skips files matching `glob`, even if included.
+    pub fn exclude(mut self, glob: impl Into<String>) -> Self {
+        self.exclude.push(glob.into());
+        self
+    }
+
+    /// Whether to skip files ignored by `.gitignore` (default: true). As with
+    /// git, ignore files only apply within a repository.
+    pub fn gitignore(mut self, gitignore: bool) -> Self {
+        self.gitignore = gitignore;
+        self
+    }
+
+    /// Whether to descend into symbolically linked directories.
+    pub fn follow_symlinks(mut self, follow_symlinks: bool) -> Self {
+        self.follow_symlinks = follow_symlinks;
+        self
+    }
+
+    /// Sets the syntax of files matching no glob given to
+    /// [`Generator::dialect`] (default: NASM).
+    pub fn syntax(mut self, dialect: Dialect) -> Self {
+        self.syntax = dialect;
+        self
+    }
+
+    /// Parses files matching `glob` as `dialect`. Globs are checked in the
+    /// order given.
+    pub fn dialect(
+        mut self, glob: impl Into<String>, dialect: Dialect
+    ) -> Self {
+        self.dialects.insert(glob.into(), dialect);
+        self
+    }
+
+    /// Adds a directory searched for `%include`d files.
+    pub fn include_path(mut self, directory: impl Into<PathBuf>) -> Self {
+        self.include_paths.push(directory.into());
+        self
+    }
+
+    /// Lists a macro predefined on the assembler command line on the front
+    /// page.
+    pub fn define(
+        mut self, name: impl Into<String>, value: impl Into<String>
+    ) -> Self {
+        self.defines.insert(name.into(), value.into());
+        self
+    }
+
+    /// Records that `library` provides `symbols`, which externs may name.
+    pub fn library<S: Into<String>>(
+        mut self, library: &str, symbols: impl IntoIterator<Item = S>
+    ) -> Self {
+        self.libraries.add(library, symbols);
+        self
+    }
+
+    /// Caches parsed files in `directory`. A cache that cannot be written
+    /// is ignored.
+    pub fn cache(mut self, directory: impl Into<PathBuf>) -> Self {
+        self.cache = Some(directory.into());
+        self
+    }
+
+    /// Annotates symbols with their addresses and sizes in the linked ELF
+    /// file or GNU ld map file at `path`, which is read on every write so
+    /// that it can be rebuilt in between.
+    pub fn layout(mut self, path: impl Into<PathBuf>) -> Self {
+        self.layout = Some(path.into());
+        self
+    }
+
+    /// Adds CSS rules to every HTML page, after the built-in stylesheet.
+    pub fn style(mut self, css: impl Into<String>) -> Self {
+        if !self.style.is_empty() {
+            self.style.push(' ');
+        }
+        self.style.push_str(&css.into());
+        self
+    }
+
+    /// Adds the rules of the stylesheet at `path` to every HTML page, after
+    /// those added by [`Generator::style`]. Like the layout, it is read on
+    /// every write.
+    pub fn stylesheet(mut self, path: impl Into<PathBuf>) -> Self {
+        self.stylesheet = Some(path.into());
+        self
+    }
+
+    /// Sets the number of threads used to discover and parse files, or 0
+    /// (the default) for one per CPU.
+    pub fn jobs(mut self, jobs: usize) -> Self {
+        self.jobs = jobs;
+        self
+    }
+
+    /// The output directory.
+    pub fn output_directory(&self) -> &Path {
+        &self.output
+    }
+
+    /// The files and directories to document.
+    pub fn input_paths(&self) -> &[PathBuf] {
+        &self.inputs
+    }
+
+    fn matchers(&self) -> Result<Matchers, ConfigError> {
+        Ok(Matchers {
+            include: glob_set(&self.include)?,
+            exclude: glob_set(&self.exclude)?,
+            dialects: glob_set(self.dialects.keys())?
+        })
+    }
+
+    fn relative<'a>(&self, path: &'a Path) -> &'a Path {
+        path.strip_prefix(&self.root).unwrap_or(path)
+    }
+
+    fn accepts(&self, matchers: &Matchers, path: &Path) -> bool {
+        let relative = self.relative(path);
+        can_parse(path)
+            && (matchers.include.is_empty()
+                || matches_within(&matchers.include, relative))
+            && !matches_within(&matchers.exclude, relative)
+    }
+
+    fn dialect_of(&self, matchers: &Matchers, file: &Path) -> Dialect {
+        matchers
+            .dialects
+            .matches(self.relative(file))
+            .into_iter()
+            .min()
+            .and_then(|i| self.dialects.values().nth(i).copied())
+            .unwrap_or(self.syntax)
+    }
+
+    /// The files to document, in path order. Directories are walked in
+    /// parallel.
+    pub fn discover(&self) -> Result<BTreeSet<PathBuf>, GenerateError> {
+        let matchers = self.matchers()?;
+        let files = Mutex::new(BTreeSet::new());
+        let walk_error = Mutex::new(None);
+        for path in &self.inputs {
+            let metadata = fs::metadata(path)
+                .map_err(|error| GenerateError::Read(path.clone(), error))?;
+            if metadata.is_dir() {
+                WalkBuilder::new(path)
+                    .standard_filters(false)
+                    .git_ignore(self.gitignore)
+                    .git_exclude(self.gitignore)
+                    .parents(self.gitignore)
+                    // as git does, so a stray ignore file above the
+                    // repository cannot hide inputs
+                    .require_git(true)
+                    .follow_links(self.follow_symlinks)
+                    .threads(self.jobs)
+                    .build_parallel()
+                    .run(|| {
+                        Box::new(|file| match file {
+                            Ok(file) => {
+                                if self.accepts(&matchers, file.path()) {
+                                    files
+                                        .lock()
+                                        .unwrap()
+                                        .insert(file.into_path());
+                                }
+                                WalkState::Continue
+                            }
+                            Err(error) => {
+                                walk_error.lock().unwrap().get_or_insert(error);
+                                WalkState::Quit
+                            }
+                        })
+                    });
+            } else if self.accepts(&matchers, path) {
+                files.lock().unwrap().insert(path.clone());
+            }
+        }
+        if let Some(error) = walk_error.into_inner().unwrap() {
+            return Err(GenerateError::Walk(error));
+        }
+        Ok(files.into_inner().unwrap())
+    }
+
+    fn parse_cached(
+        &self, file: &Path, dialect: Dialect, cache: Option<&ParseCache>
+    ) -> Result<AssemblyFile, GenerateError> {
+        let source = fs::read(file)
+            .map_err(|error| GenerateError::Read(file.to_path_buf(), error))?;
+        if let Some(asm) =
+            cache.and_then(|cache| cache.get(file, dialect, &source))
+        {
+            return Ok(asm);
+        }
+        let source = String::from_utf8(source)
+            .map_err(|_| GenerateError::InvalidUtf8(file.to_path_buf()))?;
+        let asm = Self::parse_as(file, &source, dialect)?;
+        if let Some(cache) = cache {
+            let _ = cache.put(file, dialect, source.as_bytes(), &asm);
+        }
+        Ok(asm)
+    }
+
+    fn parse_as(
+        file: &Path, source: &str, dialect: Dialect
+    ) -> Result<AssemblyFile, GenerateError> {
+        match dialect {
+            Dialect::NASM => AssemblyFile::parse::<syntax::NASM>(file, source)
+                .map_err(|error| {
+                    GenerateError::Parse(file.to_path_buf(), error)
+                })
+        }
+    }
+
+    fn parse_cache(&self) -> Option<ParseCache> {
+        self.cache.as_ref().map(|directory| {
+            ParseCache::new(directory.clone(), self.include_paths.clone())
+        })
+    }
+
+    /// Parses `file`, which need not be one found by
+    /// [`Generator::discover`].
+    pub fn parse(&self, file: &Path) -> Result<AssemblyFile, GenerateError> {
+        let dialect = self.dialect_of(&self.matchers()?, file);
+        self.parse_cached(file, dialect, self.parse_cache().as_ref())
+    }
+
+    /// Parses `source` as the contents of `file`, e.g., for an unsaved
+    /// buffer in an editor. The cache is not consulted.
+    pub fn parse_source(
+        &self, file: &Path, source: &str
+    ) -> Result<AssemblyFile, GenerateError> {
+        Self::parse_as(file, source, self.dialect_of(&self.matchers()?, file))
+    }
+
+    /// Parses `files` in parallel. If several fail, the error for the first
+    /// in path order is reported, so the outcome does not depend on
+    /// scheduling.
+    pub fn parse_all(
+        &self, files: BTreeSet<PathBuf>
+    ) -> Result<BTreeMap<PathBuf, AssemblyFile>, GenerateError> {
+        self.parse_each(files)?
+            .into_iter()
+            .map(|(file, asm)| Ok((file, asm?)))
+            .collect()
+    }
+
+    /// Parses `files` in parallel, giving the outcome for each in path
+    /// order.
+    #[allow(clippy::type_complexity)]
+    pub fn parse_each(
+        &self, files: BTreeSet<PathBuf>
+    ) -> Result<
+        Vec<(PathBuf, Result<AssemblyFile, GenerateError>)>,
+        GenerateError
+    > {
+        let matchers = self.matchers()?;
+        let cache = self.parse_cache();
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(self.jobs)
+            .build()
+            .map_err(GenerateError::ThreadPool)?;
+        Ok(pool.install(|| {
+            files
+                .into_iter()
+                .collect::<Vec<_>>()
+                .into_par_iter()
+                .map(|file| {
+                    let dialect = self.dialect_of(&matchers, &file);
+                    let asm = self.parse_cached(&file, dialect, cache.as_ref());
+                    (file, asm)
+                })
+                .collect()
+        }))
+    }
+
+    /// Wraps parsed files in a project, resolving references between them.
+    pub fn build(
+        &self, files: impl IntoIterator<Item = (PathBuf, AssemblyFile)>
+    ) -> AssemblyProject {
+        AssemblyProject::build_from(files)
+            .with_include_paths(self.include_paths.clone())
+            .with_libraries(self.libraries.clone())
+    }
+
+    /// Discovers, parses, and resolves the project.
+    pub fn project(&self) -> Result<AssemblyProject, GenerateError> {
+        Ok(self.build(self.parse_all(self.discover()?)?))
+    }
+
+    /// Writes the documentation for `project` in every format, leaving
+    /// unchanged pages untouched.
+    pub fn write(
+        &self, project: &AssemblyProject
+    ) -> Result<Pages, GenerateError> {
+        if self.output.exists() && !self.output.is_dir() {
+            return Err(GenerateError::NotADirectory(self.output.clone()));
+        }
+        let layout = self
+            .layout
+            .as_deref()
+            .map(Layout::read)
+            .transpose()
+            .map_err(GenerateError::Layout)?;
+        let mut style = self.style.clone();
+        if let Some(path) = &self.stylesheet {
+            let css = fs::read_to_string(path)
+                .map_err(|error| GenerateError::Read(path.clone(), error))?;
+            if !style.is_empty() {
+                style.push('\n');
+            }
+            style.push_str(css.trim_end());
+        }
+        let docs = project.generate_docs(layout.as_ref());
+        let index =
+            project.generate_index(&self.title, &self.defines, layout.as_ref());
+        let mut pages = Pages::default();
+        let formats = if self.formats.is_empty() {
+            &[Format::Markdown][..]
+        } else {
+            &self.formats
+        };
+        for format in formats {
+            match format {
+                Format::Markdown => self.write_pages::<Markdown>(
+                    &docs, &index, &style, &mut pages
+                )?,
+                Format::Html => {
+                    self.write_pages::<Html>(&docs, &index, &style, &mut pages)?
+                }
+                Format::Ctags => self.write_tags(project, &mut pages)?
+            }
+        }
+        Ok(pages)
+    }
+
+    /// Writes the pages for one backend.
+    fn write_pages<B: Backend>(
+        &self, docs: &[(PathBuf, Docs)], index: &Docs, style: &str,
+        pages: &mut Pages
+    ) -> Result<(), GenerateError> {
+        let file_map = output_paths(
+            docs.iter().map(|(file, _)| file),
+            self.flat,
+            B::EXTENSION
+        );
+        let index_page =
+            PathBuf::from(format!("{}.{}", INDEX_PAGE, B::EXTENSION));
+        for (page, docs) in [(&index_page, index)]
+            .into_iter()
+            .chain(docs.iter().map(|(file, docs)| (&file_map[file], docs)))
+        {
+            let path = self.output.join(page);
+            if write_atomically(&path, |sink| {
+                docs.write_to::<B>(&file_map, style, sink)
+            })
+            .map_err(|error| GenerateError::Write(path, error))?
+            {
+                pages.written += 1;
+            }
+            pages.paths.insert(page.clone());
+        }
+        Ok(())
+    }
+
+    /// Writes a tag file indexing the declarations of `project`.
+    fn write_tags(
+        &self, project: &AssemblyProject, pages: &mut Pages
+    ) -> Result<(), GenerateError> {
+        let page = PathBuf::from(TAGS_FILE);
+        let path = self.output.join(&page);
+        let written = (|| {
+            fs::create_dir_all(&self.output)?;
+            // tag files name sources relative to their own directory
+            let directory = fs::canonicalize(&self.output)?;
+            let mut tags = tags::tags(project);
+            for tag in &mut tags {
+                if let Ok(file) = fs::canonicalize(&tag.file) {
+                    tag.file = file;
+                }
+            }
+            write_atomically(&path, |sink| {
+                tags::write_ctags(&tags, &directory, sink)
+            })
+        })()
+        .map_err(|error| GenerateError::Write(path, error))?;
+        if written {
+            pages.written += 1;
+        }
+        pages.paths.insert(page);
+        Ok(())
+    }
+
+    /// Generates the documentation.
+    pub fn run(&self) -> Result<Generated, GenerateError> {
+        let project = self.project()?;
+        let pages = self.write(&project)?;
+        Ok(Generated { project, pages })
+    }
+}
+
+fn can_parse(path: &Path) -> bool {
+    path.is_file()
+        && ["nasm", "asm"].contains(
+            &path.extension().and_then(ffi::OsStr::to_str).unwrap_or("")
+        )
+}
+
+/// Whether the files at `a` and `b` have the same contents, compared a
+/// chunk at a time.
+fn same_contents(a: &Path, b: &Path) -> io::Result<bool> {
+    let (Ok(b_file), Ok(a_file)) = (File::open(b), File::open(a)) else {
+        return Ok(false);
+    };
+    if a_file.metadata()?.len() != b_file.metadata()?.len() {
+        return Ok(false);
+    }
+    let (mut a, mut b) = (BufReader::new(a_file), BufReader::new(b_file));
+    loop {
+        let chunk = a.fill_buf()?;
+        if chunk.is_empty() {
+            return Ok(b.fill_buf()?.is_empty());
+        }
+        let length = chunk.len();
+        let mut expected = vec![0; length];
+        if b.read_exact(&mut expected).is_err() || expected != chunk {
+            return Ok(false);
+        }
+        a.consume(length);
+    }
+}
+
+/// Streams the output of `write` into a temporary file beside `path` and
+/// renames it over `path` if the contents differ, so that `path` is never
+/// left half-written and unchanged files keep their modification times.
+/// Returns whether `path` was replaced.
+pub fn write_atomically(
+    path: &Path, write: impl FnOnce(&mut BufWriter<File>) -> io::Result<()>
+) -> io::Result<bool> {
+    let parent = path.parent().unwrap_or(Path::new(""));
+    fs::create_dir_all(parent)?;
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(format!(".{}.tmp", process::id()));
+    let temporary = PathBuf::from(temporary);
+
+    let result = (|| {
+        let mut writer = BufWriter::new(File::create(&temporary)?);
+        write(&mut writer)?;
+        let file = writer.into_inner().map_err(|error| error.into_error())?;
+        if same_contents(&temporary, path)? {
+            return Ok(false);
+        }
+        file.sync_all()?;
+        fs::rename(&temporary, path)?;
+        Ok(true)
+    })();
+    if !matches!(result, Ok(true)) {
+        let _ = fs::remove_file(&temporary);
+    }
+    result
+}
+
+/// Name of the front page, which lists every file, without extension.
+const INDEX_PAGE: &str = "index";
+
+/// Name of the tag file written for [`Format::Ctags`].
+const TAGS_FILE: &str = "tags";
+
+/// Chooses where to write the documentation for each file, relative to the
+/// output directory. Pages mirror the input directory structure below the
+/// files' common ancestor, or, if `flat`, are placed side by side. Each file
+/// gets the shortest name that no other file shares. Pages end in
+/// `.extension`.
+fn output_paths<'a>(
+    files: impl IntoIterator<Item = &'a PathBuf>, flat: bool, extension: &str
+) -> HashMap<PathBuf, PathBuf> {
+    let mut files = files.into_iter().collect::<Vec<_>>();
+    files.sort();
+
+    // directories are compared as absolute paths so that inputs outside the
+    // working directory, e.g., `../lib`, cannot lead out of the output
+    let dirs = files
+        .iter()
+        .map(|file| {
+            let file =
+                path::absolute(file).unwrap_or_else(|_| file.to_path_buf());
+            normalize(file.parent().unwrap_or(Path::new("")))
+                .components()
+                .filter_map(|component| match component {
+                    Component::Normal(part) => {
+                        Some(part.to_string_lossy().into_owned())
+                    }
+                    _ => None
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    let common = dirs
+        .iter()
+        .map(Vec::len)
+        .min()
+        .map(|shortest| {
+            (0..shortest)
+                .take_while(|i| dirs.iter().all(|dir| dir[*i] == dirs[0][*i]))
+                .count()
+        })
+        .unwrap_or(0);
+
+    // candidate names for the file at each index, least specific first
+    let candidates = |i: usize| -> Vec<PathBuf> {
+        let file = files[i];
+        let stem = file.file_stem().unwrap_or_default().to_string_lossy();
+        let name = file.file_name().unwrap_or_default().to_string_lossy();
+        let relative_dir = &dirs[i][common..];
+        if flat {
+            let prefix = relative_dir
+                .iter()
+                .map(|component| format!("{}-", component))
+                .collect::<String>();
+            vec![
+                PathBuf::from(format!("{}.{}", stem, extension)),
+                PathBuf::from(format!("{}{}.{}", prefix, stem, extension)),
+                PathBuf::from(format!("{}{}.{}", prefix, name, extension)),
+            ]
+        } else {
+            let dir = relative_dir.iter().collect::<PathBuf>();
+            vec![
+                dir.join(format!("{}.{}", stem, extension)),
+                dir.join(format!("{}.{}", name, extension)),
+            ]
+        }
+    };
+
+    let mut file_map = HashMap::new();
+    let mut taken =
+        HashSet::from([PathBuf::from(format!("{}.{}", INDEX_PAGE, extension))]);
+    let mut unassigned = (0..files.len()).collect::<Vec<_>>();
+    let levels = if flat { 3 } else { 2 };
+    for level in 0..levels {
+        let mut counts = HashMap::new();
+        for i in &unassigned {
+            if let Some(candidate) = candidates(*i).get(level) {
+                *counts.entry(candidate.clone()).or_insert(0) += 1;
+            }
+        }
+        unassigned.retain(|i| {
+            let candidate = &candidates(*i)[level];
+            if counts[candidate] == 1 && !taken.contains(candidate) {
+                taken.insert(candidate.clone());
+                file_map.insert(files[*i].clone(), candidate.clone());
+                false
+            } else {
+                true
+            }
+        });
+    }
+    for i in unassigned {
+        let most_specific = candidates(i).pop().unwrap();
+        let stem = most_specific.with_extension("");
+        let mut counter = 2;
+        let path = loop {
+            let path = PathBuf::from(format!(
+                "{}-{}.{}",
+                stem.display(),
+                counter,
+                extension
+            ));
+            if !taken.contains(&path) {
+                break path;
+            }
+            counter += 1;
+        };
+        taken.insert(path.clone());
+        file_map.insert(files[i].clone(), path);
+    }
+    file_map
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        error, fs, io,
+        path::{Component, PathBuf}
+    };
+
+    use super::{output_paths, GenerateError, Generator};
+
+    #[test]
+    fn missing_inputs_are_errors() {
+        let root = tempfile::tempdir().unwrap();
+        let missing = root.path().join("missing");
+        let error = Generator::new().input(&missing).discover().unwrap_err();
+        assert!(
+            matches!(&error, GenerateError::Read(path, error)
+                if *path == missing && error.kind() == io::ErrorKind::NotFound),
+            "{:?}",
+            error
+        );
+        let source = error::Error::source(&error)
+            .and_then(|source| source.downcast_ref::<io::Error>());
+        assert_eq!(source.unwrap().kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn walk_errors_are_reported() {
+        let root = tempfile::tempdir().unwrap();
+        let looping = root.path().join("looping");
+        fs::create_dir(&looping).unwrap();
+        std::os::unix::fs::symlink(&looping, looping.join("self")).unwrap();
+        let error = Generator::new()
+            .input(&looping)
+            .follow_symlinks(true)
+            .discover()
+            .unwrap_err();
+        assert!(matches!(error, GenerateError::Walk(_)), "{:?}", error);
+    }
+
+    #[test]
+    fn gitignore_in_parent_directory_applies() {
+        let root = tempfile::tempdir().unwrap();
+        let repository = root.path().join("repository");
+        let sub = repository.join("sub");
+        fs::create_dir_all(&sub).unwrap();
+        fs::write(root.path().join(".gitignore"), "*\n").unwrap();
+        fs::write(repository.join(".gitignore"), "ignored.asm\n").unwrap();
+        fs::write(sub.join("ignored.asm"), "").unwrap();
+        fs::write(sub.join("kept.asm"), "").unwrap();
+
+        // outside any repository, ignore files do not apply
+        let files = Generator::new().input(&sub).discover().unwrap();
+        assert_eq!(files.len(), 2);
+
+        // nor do those above the repository
+        fs::create_dir(repository.join(".git")).unwrap();
+        let files = Generator::new().input(&sub).discover().unwrap();
+        assert_eq!(
+            files.into_iter().collect::<Vec<_>>(),
+            [sub.join("kept.asm")]
+        );
+
+        let files = Generator::new()
+            .input(&sub)
+            .gitignore(false)
+            .discover()
+            .unwrap();
+        assert_eq!(files.len(), 2);
+    }
+
+    #[test]
+    fn pages_stay_inside_output_directory() {
+        let files = [
+            PathBuf::from("../lib/a.nasm"),
+            PathBuf::from("../../other/b.nasm"),
+            PathBuf::from("src/c.nasm"),
+            PathBuf::from("./src/../d.nasm")
+        ];
+        for flat in [false, true] {
+            let pages = output_paths(&files, flat, "md");
+            assert_eq!(pages.len(), files.len());
+            for page in pages.values() {
+                assert!(
+                    page.components().all(|component| matches!(
+                        component,
+                        Component::Normal(_)
+                    )),
+                    "{} escapes the output directory",
+                    page.display()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn pages_mirror_directories_below_common_ancestor() {
+        let files =
+            [PathBuf::from("src/a.nasm"), PathBuf::from("src/fs/b.nasm")];
+        let pages = output_paths(&files, false, "md");
+        assert_eq!(pages[&files[0]], PathBuf::from("a.md"));
+        assert_eq!(pages[&files[1]], PathBuf::from("fs/b.md"));
+    }
+}
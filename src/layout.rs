@@ -0,0 +1,344 @@
+// Copyright (C) 2024 Ethan Uppal. All rights reserved.
+
+//! Final addresses and sizes of symbols, from a linked ELF file or a GNU ld
+//! map file.
+
+use std::{
+    collections::HashMap,
+    error,
+    fmt::{self, Display},
+    fs, io,
+    path::{Path, PathBuf}
+};
+
+use object::{FileKind, Object, ObjectSection, ObjectSymbol, SymbolKind};
+
+use crate::{docs::Visibility, verify::is_source};
+
+#[derive(Debug)]
+pub enum LayoutError {
+    Read(PathBuf, io::Error),
+    Parse(PathBuf, object::Error),
+    /// The file is neither an ELF file nor a map file listing any symbols.
+    Unrecognized(PathBuf)
+}
+
+impl Display for LayoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Read(path, error) => {
+                write!(f, "Could not read {}: {}", path.display(), error)
+            }
+            Self::Parse(path, error) => {
+                write!(f, "Could not parse {}: {}", path.display(), error)
+            }
+            Self::Unrecognized(path) => write!(
+                f,
+                "{} is neither an ELF file nor a linker map file",
+                path.display()
+            )
+        }
+    }
+}
+
+impl error::Error for LayoutError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::Read(_, error) => Some(error),
+            Self::Parse(_, error) => Some(error),
+            Self::Unrecognized(_) => None
+        }
+    }
+}
+
+/// Where a symbol ended up in the linked binary.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Placement {
+    pub address: u64,
+    /// Size in bytes, which when the assembler did not record it is the
+    /// distance to the next non-local symbol in the same section.
+    pub size: u64
+}
+
+/// A symbol as read, before sizes are inferred.
+struct Entry {
+    name: String,
+    address: u64,
+    size: u64,
+    /// Index into the regions, i.e., sections or input sections, that
+    /// bound the symbol.
+    region: usize,
+    is_global: bool,
+    /// The source file the symbol came from, for local symbols.
+    source: Option<String>
+}
+
+/// The placements of the symbols of a linked binary.
+#[derive(Debug, Default)]
+pub struct Layout {
+    globals: HashMap<String, Placement>,
+    /// Local symbols, by the name of the source file defining them and then
+    /// by name.
+    locals: Vec<(String, HashMap<String, Placement>)>
+}
+
+impl Layout {
+    /// Reads the ELF file or GNU ld map file at `path`.
+    pub fn read(path: &Path) -> Result<Self, LayoutError> {
+        let data = fs::read(path)
+            .map_err(|error| LayoutError::Read(path.into(), error))?;
+        let layout = match FileKind::parse(&*data) {
+            Ok(FileKind::Elf32 | FileKind::Elf64) => {
+                let file = object::File::parse(&*data)
+                    .map_err(|error| LayoutError::Parse(path.into(), error))?;
+                Self::from_elf(&file)
+            }
+            _ => match std::str::from_utf8(&data) {
+                Ok(text) => Self::from_map(text),
+                Err(_) => Self::default()
+            }
+        };
+        if layout.globals.is_empty() && layout.locals.is_empty() {
+            return Err(LayoutError::Unrecognized(path.into()));
+        }
+        Ok(layout)
+    }
+
+    fn from_elf(file: &object::File) -> Self {
+        let mut regions = Vec::new();
+        let mut region_of = HashMap::new();
+        for section in file.sections() {
+            region_of.insert(section.index(), regions.len());
+            regions
+                .push((section.address(), section.address() + section.size()));
+        }
+        let mut entries = Vec::new();
+        let mut source = None;
+        for symbol in file.symbols() {
+            let Ok(name) = symbol.name() else {
+                continue;
+            };
+            if symbol.kind() == SymbolKind::File {
+                source = Some(name.to_string());
+                continue;
+            }
+            let region = symbol
+                .section_index()
+                .and_then(|index| region_of.get(&index));
+            let Some(&region) = region else {
+                continue;
+            };
+            if name.is_empty() || symbol.kind() == SymbolKind::Section {
+                continue;
+            }
+            entries.push(Entry {
+                name: name.to_string(),
+                address: symbol.address(),
+                size: symbol.size(),
+                region,
+                is_global: symbol.is_global(),
+                source: source.clone()
+            });
+        }
+        Self::from_entries(entries, &regions)
+    }
+
+    /// Reads the memory map of a GNU ld `-Map` file, which lists the global
+    /// symbols of each input section.
+    fn from_map(text: &str) -> Self {
+        let hex = |word: &str| {
+            word.strip_prefix("0x")
+                .and_then(|digits| u64::from_str_radix(digits, 16).ok())
+        };
+        let mut regions = Vec::new();
+        let mut entries = Vec::new();
+        let mut in_map = false;
+        for line in text.lines() {
+            if line.starts_with("Linker script and memory map") {
+                in_map = true;
+                continue;
+            }
+            if !in_map || !line.starts_with(' ') {
+                continue;
+            }
+            let words = line.split_whitespace().collect::<Vec<_>>();
+            // input sections, whose name may be on the line before
+            let section = match words.as_slice() {
+                [name, address, size, ..] if name.starts_with('.') => {
+                    hex(address).zip(hex(size))
+                }
+                [address, size, _file] => hex(address).zip(hex(size)),
+                _ => None
+            };
+            if let Some((address, size)) = section {
+                regions.push((address, address + size));
+                continue;
+            }
+            if let [address, name] = words.as_slice() {
+                let is_symbol = name
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || "_.$@?".contains(c));
+                if let (Some(address), true, false) =
+                    (hex(address), is_symbol, regions.is_empty())
+                {
+                    entries.push(Entry {
+                        name: name.to_string(),
+                        address,
+                        size: 0,
+                        region: regions.len() - 1,
+                        is_global: true,
+                        source: None
+                    });
+                }
+            }
+        }
+        Self::from_entries(entries, &regions)
+    }
+
+    /// Infers missing sizes and indexes the symbols.
+    fn from_entries(mut entries: Vec<Entry>, regions: &[(u64, u64)]) -> Self {
+        entries.sort_by_key(|entry| (entry.region, entry.address));
+        let mut layout = Self::default();
+        for (i, entry) in entries.iter().enumerate() {
+            let size = if entry.size != 0 {
+                entry.size
+            } else {
+                // local labels such as `strlen.loop` lie within `strlen`
+                let constituent_prefix = format!("{}.", entry.name);
+                let end = entries[i + 1..]
+                    .iter()
+                    .take_while(|next| next.region == entry.region)
+                    .find(|next| {
+                        next.address > entry.address
+                            && !next.name.starts_with(&constituent_prefix)
+                    })
+                    .map_or(regions[entry.region].1, |next| next.address);
+                end.saturating_sub(entry.address)
+            };
+            let placement = Placement {
+                address: entry.address,
+                size
+            };
+            if entry.is_global {
+                layout
+                    .globals
+                    .entry(entry.name.clone())
+                    .or_insert(placement);
+            } else if let Some(source) = &entry.source {
+                let index = match layout
+                    .locals
+                    .iter()
+                    .position(|(other, _)| other == source)
+                {
+                    Some(index) => index,
+                    None => {
+                        layout.locals.push((source.clone(), HashMap::new()));
+                        layout.locals.len() - 1
+                    }
+                };
+                layout.locals[index]
+                    .1
+                    .entry(entry.name.clone())
+                    .or_insert(placement);
+            }
+        }
+        layout
+    }
+
+    /// Where the symbol `name` of `file` was placed, if it was linked.
+    pub fn placement(
+        &self, file: &Path, name: &str, visibility: Visibility
+    ) -> Option<Placement> {
+        match visibility {
+            Visibility::Global => self.globals.get(name).copied(),
+            Visibility::Private => self
+                .locals
+                .iter()
+                .filter(|(source, _)| is_source(file, source))
+                .find_map(|(_, symbols)| symbols.get(name).copied()),
+            Visibility::External => None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{Entry, Layout, Placement};
+    use crate::docs::Visibility;
+
+    /// An excerpt of the `-Map` output of GNU ld for a small program.
+    const MAP: &str = include_str!("../tests/fixtures/app.map");
+
+    #[test]
+    fn map_file_places_globals_of_input_sections() {
+        let layout = Layout::from_map(MAP);
+        let file = Path::new("src/lib.nasm");
+        let placement = |name| layout.placement(file, name, Visibility::Global);
+        assert_eq!(
+            placement("_start"),
+            Some(Placement {
+                address: 0x401000,
+                size: 6
+            })
+        );
+        // the last symbol of an input section runs to its end
+        assert_eq!(
+            placement("strlen"),
+            Some(Placement {
+                address: 0x401006,
+                size: 7
+            })
+        );
+        assert_eq!(
+            placement("table"),
+            Some(Placement {
+                address: 0x402000,
+                size: 0x14
+            })
+        );
+        // assignments are not symbols, and map files list no locals
+        assert_eq!(placement("_edata"), None);
+        assert_eq!(placement("__bss_start"), None);
+        assert_eq!(layout.placement(file, "helper", Visibility::Private), None);
+    }
+
+    #[test]
+    fn sizes_are_inferred_from_next_symbol() {
+        let entry = |name: &str, address, size, region, is_global| Entry {
+            name: name.into(),
+            address,
+            size,
+            region,
+            is_global,
+            source: (!is_global).then(|| "src/lib.nasm".into())
+        };
+        let entries = vec![
+            entry("table", 0x2000, 0, 1, true),
+            entry("sized", 0x1020, 4, 0, true),
+            entry("helper", 0x1010, 0, 0, false),
+            entry("strlen.loop", 0x1008, 0, 0, false),
+            entry("strlen", 0x1000, 0, 0, true),
+        ];
+        let layout = Layout::from_entries(
+            entries,
+            &[(0x1000, 0x1040), (0x2000, 0x2010)]
+        );
+
+        let file = Path::new("/project/src/lib.nasm");
+        let size = |name, visibility| {
+            layout
+                .placement(file, name, visibility)
+                .map(|placement| placement.size)
+        };
+        // local labels within `strlen` do not end it
+        assert_eq!(size("strlen", Visibility::Global), Some(0x10));
+        assert_eq!(size("strlen.loop", Visibility::Private), Some(8));
+        assert_eq!(size("helper", Visibility::Private), Some(0x10));
+        // recorded sizes are kept
+        assert_eq!(size("sized", Visibility::Global), Some(4));
+        // the last symbol of a region runs to its end
+        assert_eq!(size("table", Visibility::Global), Some(0x10));
+    }
+}
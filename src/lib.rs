@@ -2,6 +2,19 @@
 
 pub mod assembly_file;
 pub mod assembly_project;
+pub mod cache;
 pub mod cli;
+pub mod config;
+pub mod coverage;
 pub mod docs;
+pub mod generator;
+pub mod layout;
+pub mod library;
+pub mod lint;
+pub mod lsp;
+pub mod register;
+pub mod register_usage;
+pub mod serve;
 pub mod syntax;
+pub mod tags;
+pub mod verify;
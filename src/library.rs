@@ -0,0 +1,133 @@
+// Copyright (C) 2024 Ethan Uppal. All rights reserved.
+
+use std::collections::HashMap;
+
+/// Commonly used symbols of the C standard library and POSIX.
+const LIBC: &[&str] = &[
+    "abort",
+    "abs",
+    "atexit",
+    "atof",
+    "atoi",
+    "atol",
+    "bsearch",
+    "calloc",
+    "clock",
+    "clock_gettime",
+    "close",
+    "environ",
+    "errno",
+    "__errno_location",
+    "execve",
+    "exit",
+    "_exit",
+    "fclose",
+    "fflush",
+    "fgetc",
+    "fgets",
+    "fopen",
+    "fork",
+    "fprintf",
+    "fputc",
+    "fputs",
+    "fread",
+    "free",
+    "fscanf",
+    "fseek",
+    "ftell",
+    "fwrite",
+    "getchar",
+    "getenv",
+    "getpid",
+    "isalpha",
+    "isdigit",
+    "isspace",
+    "lseek",
+    "malloc",
+    "memchr",
+    "memcmp",
+    "memcpy",
+    "memmove",
+    "memset",
+    "mmap",
+    "munmap",
+    "open",
+    "perror",
+    "printf",
+    "pthread_create",
+    "pthread_join",
+    "putchar",
+    "puts",
+    "qsort",
+    "rand",
+    "read",
+    "realloc",
+    "scanf",
+    "snprintf",
+    "sprintf",
+    "srand",
+    "sscanf",
+    "stderr",
+    "stdin",
+    "stdout",
+    "strcat",
+    "strchr",
+    "strcmp",
+    "strcpy",
+    "strdup",
+    "strerror",
+    "strlen",
+    "strncat",
+    "strncmp",
+    "strncpy",
+    "strrchr",
+    "strstr",
+    "strtod",
+    "strtol",
+    "strtoul",
+    "system",
+    "time",
+    "tolower",
+    "toupper",
+    "vfprintf",
+    "vprintf",
+    "write"
+];
+
+/// Symbols provided by libraries outside the project, so that externs naming
+/// them are known rather than unresolved.
+#[derive(Debug, Clone)]
+pub struct Libraries {
+    /// The library providing each symbol.
+    providers: HashMap<String, String>
+}
+
+impl Default for Libraries {
+    /// Knows the common symbols of libc.
+    fn default() -> Self {
+        let mut libraries = Self {
+            providers: HashMap::new()
+        };
+        libraries.add("libc", LIBC.iter().copied());
+        libraries
+    }
+}
+
+impl Libraries {
+    /// Records that `library` provides `symbols`. Symbols already known keep
+    /// their first provider.
+    pub fn add<S: Into<String>>(
+        &mut self, library: &str, symbols: impl IntoIterator<Item = S>
+    ) {
+        for symbol in symbols {
+            self.providers
+                .entry(symbol.into())
+                .or_insert_with(|| library.to_string());
+        }
+    }
+
+    /// The library providing `symbol`, if known.
+    pub fn provider(&self, symbol: &str) -> Option<&str> {
+        self.providers.get(symbol).map(String::as_str)
+    }
+}
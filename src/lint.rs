@@ -0,0 +1,229 @@
+// Copyright (C) 2024 Ethan Uppal. All rights reserved.
+
+use std::{
+    fmt::{self, Display},
+    path::PathBuf
+};
+
+use crate::{
+    assembly_project::{AssemblyProject, ExternSource},
+    docs::Visibility,
+    register::Register,
+    register_usage::UsageMismatch
+};
+
+/// A problem with the documentation or declarations of a project.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Lint {
+    /// A global symbol has no documentation comment.
+    UndocumentedGlobal(String),
+    /// A routine's `@param` names a register the routine never reads.
+    UnreadParam { routine: String, register: Register },
+    /// A routine's documented register usage disagrees with its code.
+    RegisterUsage {
+        routine: String,
+        mismatch: UsageMismatch
+    },
+    /// A `global` has no matching label or constant in its file.
+    MissingDefinition(String),
+    /// An `extern` is neither defined in the project nor by a known library.
+    UnresolvedExtern(String),
+    /// An `extern` is never referenced in its file.
+    UnusedExtern(String),
+    /// A global is also defined in other files, which a linker would reject.
+    DuplicateGlobal { name: String, others: Vec<PathBuf> },
+    /// A documented global is missing from the symbol table of an object
+    /// assembled from its file.
+    NotExported(String),
+    /// An object exports a symbol that is undocumented in its source.
+    UndocumentedExport(String),
+    /// An object exports a symbol that no source file defines as a global.
+    UnknownExport(String),
+    /// An object records a different size for a symbol than its data
+    /// directives add up to.
+    SizeMismatch {
+        name: String,
+        computed: usize,
+        object: u64
+    }
+}
+
+impl Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UndocumentedGlobal(name) => {
+                write!(f, "global `{}` is undocumented", name)
+            }
+            Self::UnreadParam { routine, register } => write!(
+                f,
+                "`{}` documents `@param {}` but never reads it",
+                routine, register
+            ),
+            Self::RegisterUsage { routine, mismatch } => {
+                write!(f, "`{}` {}", routine, mismatch)
+            }
+            Self::MissingDefinition(name) => {
+                write!(f, "global `{}` is declared but never defined", name)
+            }
+            Self::UnresolvedExtern(name) => {
+                write!(f, "extern `{}` is not defined anywhere", name)
+            }
+            Self::UnusedExtern(name) => {
+                write!(f, "extern `{}` is never referenced", name)
+            }
+            Self::DuplicateGlobal { name, others } => {
+                write!(f, "global `{}` is also defined in ", name)?;
+                for (i, other) in others.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", other.display())?;
+                }
+                Ok(())
+            }
+            Self::NotExported(name) => {
+                write!(f, "global `{}` is documented but not exported", name)
+            }
+            Self::UndocumentedExport(name) => {
+                write!(f, "exported symbol `{}` is undocumented", name)
+            }
+            Self::UnknownExport(name) => write!(
+                f,
+                "exported symbol `{}` is not a global of any source file",
+                name
+            ),
+            Self::SizeMismatch {
+                name,
+                computed,
+                object
+            } => write!(
+                f,
+                "`{}` is {} byte{} in the object but its data is {} byte{}",
+                name,
+                object,
+                if *object == 1 { "" } else { "s" },
+                computed,
+                if *computed == 1 { "" } else { "s" }
+            )
+        }
+    }
+}
+
+/// A lint found at a location in a file.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub file: PathBuf,
+    pub line: Option<usize>,
+    pub lint: Lint
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.file.display())?;
+        if let Some(line) = self.line {
+            write!(f, ":{}", line)?;
+        }
+        write!(f, ": warning: {}", self.lint)
+    }
+}
+
+/// Reports each definition of a global defined in more than one file.
+pub fn duplicate_globals(project: &AssemblyProject) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    for (name, files) in project.duplicate_globals() {
+        for file in files {
+            let line = project.files()[file].lines.globals.get(name).copied();
+            diagnostics.push(Diagnostic {
+                file: file.clone(),
+                line,
+                lint: Lint::DuplicateGlobal {
+                    name: name.to_string(),
+                    others: files
+                        .iter()
+                        .filter(|other| *other != file)
+                        .cloned()
+                        .collect()
+                }
+            });
+        }
+    }
+    diagnostics
+}
+
+/// Lints every file of `project`, returning diagnostics ordered by location.
+pub fn check(project: &AssemblyProject) -> Vec<Diagnostic> {
+    let mut diagnostics = duplicate_globals(project);
+    for (file, asm) in project.files() {
+        let mut report = |line: Option<&usize>, lint| {
+            diagnostics.push(Diagnostic {
+                file: file.clone(),
+                line: line.copied(),
+                lint
+            })
+        };
+        let symbols = project.symbols(file);
+
+        for global in project.undefined_globals(file) {
+            report(
+                asm.lines.globals.get(global),
+                Lint::MissingDefinition(global.to_string())
+            );
+        }
+
+        for extern_ in &asm.externs {
+            if project.extern_source(extern_) == ExternSource::Unresolved {
+                report(
+                    asm.lines.externs.get(extern_),
+                    Lint::UnresolvedExtern(extern_.clone())
+                );
+            }
+            if !asm.references.contains(extern_) {
+                report(
+                    asm.lines.externs.get(extern_),
+                    Lint::UnusedExtern(extern_.clone())
+                );
+            }
+        }
+
+        for (name, symbol) in symbols.into_iter().flatten() {
+            let line = asm.lines.labels.get(name);
+            if symbol.visibility == Visibility::Global && symbol.doc.is_none() {
+                report(
+                    line.or(asm.lines.globals.get(name)),
+                    Lint::UndocumentedGlobal(name.clone())
+                );
+            }
+            let Some(usage) = &symbol.registers else {
+                continue;
+            };
+            for mismatch in usage.mismatches(symbol.doc.as_ref()) {
+                report(
+                    line,
+                    Lint::RegisterUsage {
+                        routine: name.clone(),
+                        mismatch
+                    }
+                );
+            }
+            let params = symbol.doc.iter().flat_map(|doc| doc.tag("param"));
+            for param in params {
+                let Some(register) =
+                    param.split_whitespace().next().and_then(Register::parse)
+                else {
+                    continue;
+                };
+                if !usage.read.contains(&register.full()) {
+                    report(
+                        line,
+                        Lint::UnreadParam {
+                            routine: name.clone(),
+                            register
+                        }
+                    );
+                }
+            }
+        }
+    }
+    diagnostics.sort_by(|a, b| (&a.file, a.line).cmp(&(&b.file, b.line)));
+    diagnostics
+}
@@ -0,0 +1,934 @@
+// Copyright (C) 2024 Ethan Uppal. All rights reserved.
+
+//! A language server for assembly projects, speaking LSP over stdio.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    error, fmt, fs, io,
+    ops::Range,
+    path::{Path, PathBuf}
+};
+
+use logos::Logos;
+use lsp_server::{
+    Connection, Message, Notification, ProtocolError, Request, RequestId,
+    Response
+};
+use lsp_types::{
+    notification::{
+        DidChangeTextDocument, DidChangeWatchedFiles, DidCloseTextDocument,
+        DidOpenTextDocument, Notification as _
+    },
+    request::{
+        DocumentSymbolRequest, GotoDefinition, HoverRequest, References,
+        RegisterCapability, Request as _, WorkspaceSymbolRequest
+    },
+    DidChangeWatchedFilesRegistrationOptions, DocumentSymbol,
+    DocumentSymbolParams, DocumentSymbolResponse, FileSystemWatcher,
+    GlobPattern, GotoDefinitionParams, GotoDefinitionResponse, Hover,
+    HoverContents, HoverParams, HoverProviderCapability, InitializeParams,
+    Location, MarkupContent, MarkupKind, OneOf, Position, ReferenceParams,
+    Registration, RegistrationParams, ServerCapabilities, SymbolInformation,
+    SymbolKind, TextDocumentSyncCapability, TextDocumentSyncKind, Url,
+    WorkspaceSymbolParams, WorkspaceSymbolResponse
+};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    assembly_project::{AssemblyProject, ExternSource},
+    docs::Visibility,
+    generator::{GenerateError, Generator},
+    syntax::nasm::{LineIndex, NASMTokenType}
+};
+
+#[derive(Debug)]
+pub enum LspError {
+    Protocol(ProtocolError),
+    Io(io::Error),
+    /// The project could not be discovered.
+    Generate(GenerateError),
+    /// The client went away without shutting the server down.
+    Disconnected
+}
+
+impl fmt::Display for LspError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Protocol(error) => write!(f, "Protocol error: {}", error),
+            Self::Io(error) => write!(f, "I/O error: {}", error),
+            Self::Generate(error) => error.fmt(f),
+            Self::Disconnected => write!(f, "Client disconnected")
+        }
+    }
+}
+
+impl error::Error for LspError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::Protocol(error) => Some(error),
+            Self::Io(error) => Some(error),
+            // displayed as the wrapped error itself
+            Self::Generate(error) => error.source(),
+            Self::Disconnected => None
+        }
+    }
+}
+
+impl From<ProtocolError> for LspError {
+    fn from(error: ProtocolError) -> Self {
+        Self::Protocol(error)
+    }
+}
+
+impl From<GenerateError> for LspError {
+    fn from(error: GenerateError) -> Self {
+        Self::Generate(error)
+    }
+}
+
+/// What a name is declared as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DefinitionKind {
+    Label,
+    /// A label beginning with `.`, scoped to the preceding label.
+    LocalLabel,
+    Constant,
+    Define,
+    Macro,
+    Struct
+}
+
+/// A declaration found in the source text.
+struct Definition {
+    name: String,
+    kind: DefinitionKind,
+    /// The span of the name.
+    span: Range<usize>,
+    /// For local labels, the label they belong to.
+    parent: Option<String>
+}
+
+struct Token {
+    ty: NASMTokenType,
+    span: Range<usize>
+}
+
+/// The text of a file, as open in the editor or else as on disk.
+struct Document {
+    text: String,
+    lines: LineIndex,
+    tokens: Vec<Token>,
+    definitions: Vec<Definition>
+}
+
+impl Document {
+    fn new(text: String) -> Self {
+        let lines = LineIndex::new(&text);
+        let mut tokens = Vec::new();
+        let mut lexer = NASMTokenType::lexer(&text);
+        while let Some(ty) = lexer.next() {
+            // tolerate text the parser would reject while it is being edited
+            match ty {
+                Ok(NASMTokenType::Whitespace) | Err(_) => {}
+                Ok(ty) => tokens.push(Token {
+                    ty,
+                    span: lexer.span()
+                })
+            }
+        }
+        let definitions = Self::definitions(&text, &tokens);
+        Self {
+            text,
+            lines,
+            tokens,
+            definitions
+        }
+    }
+
+    /// Finds declarations from the shape of each line, as the parser does.
+    fn definitions(text: &str, tokens: &[Token]) -> Vec<Definition> {
+        let mut definitions = Vec::new();
+        let mut parent = None;
+        for (i, token) in tokens.iter().enumerate() {
+            let at_line_start =
+                i == 0 || tokens[i - 1].ty == NASMTokenType::Newline;
+            let next = tokens.get(i + 1);
+            let next_ty = next.map(|next| next.ty);
+            let value = |token: &Token| text[token.span.clone()].to_string();
+            let (kind, name) = match (token.ty, next_ty) {
+                (
+                    NASMTokenType::Symbol,
+                    Some(
+                        NASMTokenType::Colon
+                        | NASMTokenType::DataDefine
+                        | NASMTokenType::DataReserve
+                        | NASMTokenType::Times
+                    )
+                ) if at_line_start => {
+                    let name = value(token);
+                    if name.starts_with('.') {
+                        (DefinitionKind::LocalLabel, name)
+                    } else {
+                        parent = Some(name.clone());
+                        (DefinitionKind::Label, name)
+                    }
+                }
+                (NASMTokenType::Symbol, Some(NASMTokenType::Mnemonic))
+                    if at_line_start
+                        && next.is_some_and(|next| {
+                            text[next.span.clone()].eq_ignore_ascii_case("equ")
+                        }) =>
+                {
+                    (DefinitionKind::Constant, value(token))
+                }
+                (NASMTokenType::Macro, Some(NASMTokenType::MacroCall)) => {
+                    (DefinitionKind::Macro, value(&tokens[i + 1]))
+                }
+                (NASMTokenType::Define, Some(NASMTokenType::Symbol)) => {
+                    (DefinitionKind::Define, value(&tokens[i + 1]))
+                }
+                (NASMTokenType::Struc, Some(NASMTokenType::Symbol)) => {
+                    (DefinitionKind::Struct, value(&tokens[i + 1]))
+                }
+                _ => continue
+            };
+            let span = match kind {
+                DefinitionKind::Label
+                | DefinitionKind::LocalLabel
+                | DefinitionKind::Constant => token.span.clone(),
+                _ => tokens[i + 1].span.clone()
+            };
+            let parent = (kind == DefinitionKind::LocalLabel)
+                .then(|| parent.clone())
+                .flatten();
+            definitions.push(Definition {
+                name,
+                kind,
+                span,
+                parent
+            });
+        }
+        definitions
+    }
+
+    /// The LSP position of `offset`, in UTF-16 code units.
+    fn position(&self, offset: usize) -> Position {
+        let (line, _) = self.lines.line_col(offset);
+        let start = self.lines.line_start(line).unwrap_or(0);
+        let character = self.text[start..offset].encode_utf16().count();
+        Position::new((line - 1) as u32, character as u32)
+    }
+
+    fn range(&self, span: &Range<usize>) -> lsp_types::Range {
+        lsp_types::Range::new(
+            self.position(span.start),
+            self.position(span.end)
+        )
+    }
+
+    /// The offset of an LSP position.
+    fn offset(&self, position: Position) -> usize {
+        let Some(start) = self.lines.line_start(position.line as usize + 1)
+        else {
+            return self.text.len();
+        };
+        let mut character = 0;
+        for (i, c) in self.text[start..].char_indices() {
+            if character >= position.character as usize || c == '\n' {
+                return start + i;
+            }
+            character += c.len_utf16();
+        }
+        self.text.len()
+    }
+
+    /// The name under the cursor at `offset`, with the label it lies within.
+    fn name_at(&self, offset: usize) -> Option<(String, Option<String>)> {
+        let token = self.tokens.iter().find(|token| {
+            matches!(token.ty, NASMTokenType::Symbol | NASMTokenType::MacroCall)
+                && token.span.start <= offset
+                && offset <= token.span.end
+        })?;
+        let parent = self
+            .definitions
+            .iter()
+            .take_while(|definition| definition.span.start <= offset)
+            .filter(|definition| definition.kind == DefinitionKind::Label)
+            .last()
+            .map(|definition| definition.name.clone());
+        Some((self.text[token.span.clone()].to_string(), parent))
+    }
+
+    /// The spans at which `name` is used or declared.
+    fn occurrences<'a>(
+        &'a self, name: &'a str
+    ) -> impl Iterator<Item = &'a Range<usize>> {
+        self.tokens
+            .iter()
+            .filter(move |token| {
+                matches!(
+                    token.ty,
+                    NASMTokenType::Symbol | NASMTokenType::MacroCall
+                ) && self.text[token.span.clone()] == *name
+            })
+            .map(|token| &token.span)
+    }
+}
+
+/// The state of the server: the project and the text of each of its files.
+struct Server<'a> {
+    generator: &'a Generator,
+    project: AssemblyProject,
+    documents: HashMap<PathBuf, Document>,
+    /// Files open in the editor, whose text may differ from that on disk.
+    open: BTreeSet<PathBuf>
+}
+
+fn url(file: &Path) -> Option<Url> {
+    Url::from_file_path(file).ok()
+}
+
+/// The path of `uri`, canonicalized to match discovered files.
+fn path(uri: &Url) -> Option<PathBuf> {
+    let path = uri.to_file_path().ok()?;
+    Some(fs::canonicalize(&path).unwrap_or(path))
+}
+
+impl<'a> Server<'a> {
+    fn new(generator: &'a Generator) -> Result<Self, GenerateError> {
+        let mut server = Self {
+            generator,
+            project: AssemblyProject::default(),
+            documents: HashMap::new(),
+            open: BTreeSet::new()
+        };
+        let parsed = generator.parse_each(generator.discover()?)?;
+        let mut files = Vec::new();
+        for (file, asm) in parsed {
+            match asm {
+                Ok(asm) => files.push((file, asm)),
+                Err(error) => eprintln!("{}", error)
+            }
+        }
+        for (file, _) in &files {
+            server.load(file);
+        }
+        server.project = generator.build(files);
+        Ok(server)
+    }
+
+    /// Reads `file` from disk, unless it is open.
+    fn load(&mut self, file: &Path) {
+        if self.open.contains(file) {
+            return;
+        }
+        match fs::read_to_string(file) {
+            Ok(text) => {
+                self.documents
+                    .insert(file.to_path_buf(), Document::new(text));
+            }
+            Err(_) => {
+                self.documents.remove(file);
+            }
+        }
+    }
+
+    /// Reparses `file` from its current text, keeping the previous version
+    /// in the project if it does not parse.
+    fn reparse(&mut self, file: &Path) {
+        let asm = match self.documents.get(file) {
+            Some(document) => self.generator.parse_source(file, &document.text),
+            None => {
+                self.project.update([(file.to_path_buf(), None)]);
+                return;
+            }
+        };
+        match asm {
+            Ok(asm) => self.project.update([(file.to_path_buf(), Some(asm))]),
+            Err(error) => eprintln!("{}", error)
+        }
+    }
+
+    fn location(&self, file: &Path, span: &Range<usize>) -> Option<Location> {
+        let document = self.documents.get(file)?;
+        Some(Location::new(url(file)?, document.range(span)))
+    }
+
+    /// The declarations that `name`, used in `file` within the label
+    /// `parent`, refers to.
+    fn targets(
+        &self, file: &Path, name: &str, parent: Option<&str>
+    ) -> Vec<(&Path, &Definition)> {
+        let Some((file, document)) = self.documents.get_key_value(file) else {
+            return Vec::new();
+        };
+        if name.starts_with('.') {
+            let local = in_file(file, document, name)
+                .into_iter()
+                .filter(|(_, definition)| {
+                    definition.parent.as_deref() == parent
+                })
+                .collect::<Vec<_>>();
+            return if local.is_empty() {
+                in_file(file, document, name)
+            } else {
+                local
+            };
+        }
+        let here = in_file(file, document, name);
+        if !here.is_empty() {
+            return here;
+        }
+        // otherwise, what the project says defines the global, extern, or
+        // other name, with the spans of those declarations
+        let mut files = self.project.definitions(name).to_vec();
+        if files.is_empty() {
+            files = self
+                .project
+                .macros()
+                .filter(|(_, macro_)| macro_.name == name)
+                .map(|(file, _)| file.to_path_buf())
+                .chain(
+                    self.project
+                        .files()
+                        .iter()
+                        .filter(|(_, asm)| {
+                            asm.defines.iter().any(|define| define == name)
+                                || asm
+                                    .structs
+                                    .iter()
+                                    .any(|structure| structure.name == name)
+                        })
+                        .map(|(file, _)| file.clone())
+                )
+                .collect();
+            files.sort();
+            files.dedup();
+        }
+        files
+            .iter()
+            .filter_map(|other| self.documents.get_key_value(other))
+            .flat_map(|(other, document)| in_file(other, document, name))
+            .filter(|(_, definition)| {
+                definition.kind != DefinitionKind::LocalLabel
+            })
+            .collect()
+    }
+
+    /// The name at `position` in the file of `uri`, with the label it lies
+    /// within.
+    fn name_at(
+        &self, uri: &Url, position: Position
+    ) -> Option<(PathBuf, String, Option<String>)> {
+        let file = path(uri)?;
+        let document = self.documents.get(&file)?;
+        let (name, parent) = document.name_at(document.offset(position))?;
+        Some((file, name, parent))
+    }
+
+    fn hover(&self, params: HoverParams) -> Option<Hover> {
+        let position = params.text_document_position_params;
+        let (file, name, parent) =
+            self.name_at(&position.text_document.uri, position.position)?;
+        let targets = self.targets(&file, &name, parent.as_deref());
+
+        let mut lines = vec![format!("```nasm\n{}\n```", name)];
+        let Some((target, definition)) = targets.first() else {
+            match self.project.extern_source(&name) {
+                ExternSource::Library(library) => {
+                    lines.push(format!("extern provided by `{}`", library))
+                }
+                _ if self.project.symbol(&file, &name).is_some() => {
+                    lines.push("**warning:** unresolved extern".into())
+                }
+                _ => return None
+            }
+            return Some(markdown(lines));
+        };
+        let symbol = self.project.symbol(target, &name);
+        let description = match (definition.kind, symbol) {
+            (DefinitionKind::Label, Some(symbol)) => {
+                let kind = if symbol.registers.is_some() {
+                    "routine"
+                } else {
+                    "label"
+                };
+                match &symbol.section {
+                    Some(section) => {
+                        format!("{} {} in {}", symbol.visibility, kind, section)
+                    }
+                    None => format!("{} {}", symbol.visibility, kind)
+                }
+            }
+            (DefinitionKind::LocalLabel, _) => "local label".into(),
+            (DefinitionKind::Constant, _) => "constant".into(),
+            (DefinitionKind::Define, _) => "define".into(),
+            (DefinitionKind::Macro, _) => "macro".into(),
+            (DefinitionKind::Struct, _) => "structure".into(),
+            (DefinitionKind::Label, None) => "label".into()
+        };
+        lines.push(format!("*{}* in `{}`", description, target.display()));
+        let size = symbol
+            .filter(|symbol| !symbol.data.is_empty())
+            .and_then(|symbol| symbol.data_size());
+        if let Some(size) = size {
+            lines.push(format!(
+                "{} byte{}",
+                size,
+                if size == 1 { "" } else { "s" }
+            ));
+        }
+
+        let doc = self
+            .project
+            .files()
+            .get(*target)
+            .and_then(|asm| asm.docs.get(&name));
+        if let Some(doc) = doc {
+            lines.push(doc.text.join("\n"));
+            for (tag, contents) in &doc.tags {
+                lines.push(format!("`@{}` {}", tag, contents));
+            }
+        }
+        if let Some(usage) = symbol.and_then(|symbol| symbol.registers.as_ref())
+        {
+            if !usage.clobbered.is_empty() {
+                let clobbered = usage
+                    .clobbered
+                    .iter()
+                    .map(|register| format!("`{}`", register))
+                    .collect::<Vec<_>>();
+                lines.push(format!("clobbers {}", clobbered.join(", ")));
+            }
+            for mismatch in usage.mismatches(doc) {
+                lines.push(format!("**warning:** {}", mismatch));
+            }
+        }
+        if targets.len() > 1 {
+            let others = targets[1..]
+                .iter()
+                .map(|(file, _)| format!("`{}`", file.display()))
+                .collect::<Vec<_>>();
+            lines.push(format!(
+                "**warning:** also defined in {}",
+                others.join(", ")
+            ));
+        }
+        Some(markdown(lines))
+    }
+
+    fn definition(
+        &self, params: GotoDefinitionParams
+    ) -> Option<GotoDefinitionResponse> {
+        let position = params.text_document_position_params;
+        let (file, name, parent) =
+            self.name_at(&position.text_document.uri, position.position)?;
+        let locations = self
+            .targets(&file, &name, parent.as_deref())
+            .into_iter()
+            .filter_map(|(file, definition)| {
+                self.location(file, &definition.span)
+            })
+            .collect::<Vec<_>>();
+        (!locations.is_empty())
+            .then_some(GotoDefinitionResponse::Array(locations))
+    }
+
+    fn references(&self, params: ReferenceParams) -> Option<Vec<Location>> {
+        let position = params.text_document_position;
+        let (file, name, parent) =
+            self.name_at(&position.text_document.uri, position.position)?;
+        let targets = self.targets(&file, &name, parent.as_deref());
+        let declarations = targets
+            .iter()
+            .map(|(file, definition)| (*file, definition.span.clone()))
+            .collect::<Vec<_>>();
+
+        // local labels and private symbols are only visible in their file
+        let is_private = name.starts_with('.')
+            || self
+                .project
+                .symbol(&file, &name)
+                .is_some_and(|symbol| symbol.visibility == Visibility::Private);
+        let mut files = if is_private {
+            vec![file.as_path()]
+        } else {
+            self.documents
+                .keys()
+                .map(PathBuf::as_path)
+                .filter(|other| {
+                    self.project.symbol(other, &name).is_none_or(|symbol| {
+                        symbol.visibility != Visibility::Private
+                    })
+                })
+                .collect()
+        };
+        files.sort();
+
+        let mut locations = Vec::new();
+        for other in files {
+            let document = &self.documents[other];
+            for span in document.occurrences(&name) {
+                if name.starts_with('.') {
+                    let (_, scope) = document.name_at(span.start)?;
+                    if scope != parent {
+                        continue;
+                    }
+                }
+                let is_declaration =
+                    declarations.iter().any(|(file, declaration)| {
+                        *file == other && declaration == span
+                    });
+                if is_declaration && !params.context.include_declaration {
+                    continue;
+                }
+                locations.extend(self.location(other, span));
+            }
+        }
+        Some(locations)
+    }
+
+    fn symbol_kind(&self, file: &Path, definition: &Definition) -> SymbolKind {
+        match definition.kind {
+            DefinitionKind::Label => {
+                match self.project.symbol(file, &definition.name) {
+                    Some(symbol) if symbol.registers.is_some() => {
+                        SymbolKind::FUNCTION
+                    }
+                    _ => SymbolKind::VARIABLE
+                }
+            }
+            DefinitionKind::LocalLabel => SymbolKind::KEY,
+            DefinitionKind::Constant | DefinitionKind::Define => {
+                SymbolKind::CONSTANT
+            }
+            DefinitionKind::Macro => SymbolKind::OPERATOR,
+            DefinitionKind::Struct => SymbolKind::STRUCT
+        }
+    }
+
+    #[allow(deprecated)]
+    fn document_symbol(
+        &self, params: DocumentSymbolParams
+    ) -> Option<DocumentSymbolResponse> {
+        let file = path(&params.text_document.uri)?;
+        let document = self.documents.get(&file)?;
+        let symbol = |definition: &Definition, end: usize| DocumentSymbol {
+            name: definition.name.clone(),
+            detail: None,
+            kind: self.symbol_kind(&file, definition),
+            tags: None,
+            deprecated: None,
+            range: document.range(&(definition.span.start..end)),
+            selection_range: document.range(&definition.span),
+            children: None
+        };
+
+        // each label spans up to the line of the next
+        let mut symbols: Vec<DocumentSymbol> = Vec::new();
+        let definitions = &document.definitions;
+        for (i, definition) in definitions.iter().enumerate() {
+            let end = definitions[i + 1..]
+                .iter()
+                .find(|next| next.kind != DefinitionKind::LocalLabel)
+                .map_or(document.text.len(), |next| {
+                    let (line, _) = document.lines.line_col(next.span.start);
+                    document.lines.line_start(line).unwrap_or(next.span.start)
+                })
+                .max(definition.span.end);
+            match (definition.kind, symbols.last_mut()) {
+                (DefinitionKind::LocalLabel, Some(parent))
+                    if definition.parent.as_ref() == Some(&parent.name) =>
+                {
+                    let end = definitions[i + 1..]
+                        .first()
+                        .map_or(end, |next| next.span.start)
+                        .max(definition.span.end);
+                    parent
+                        .children
+                        .get_or_insert_with(Vec::new)
+                        .push(symbol(definition, end));
+                }
+                _ => symbols.push(symbol(definition, end))
+            }
+        }
+        Some(DocumentSymbolResponse::Nested(symbols))
+    }
+
+    #[allow(deprecated)]
+    fn workspace_symbol(
+        &self, params: WorkspaceSymbolParams
+    ) -> Option<WorkspaceSymbolResponse> {
+        let query = params.query.to_lowercase();
+        let mut files = self.documents.keys().collect::<Vec<_>>();
+        files.sort();
+        let mut symbols = Vec::new();
+        for file in files {
+            for definition in &self.documents[file].definitions {
+                if definition.kind == DefinitionKind::LocalLabel
+                    || !definition.name.to_lowercase().contains(&query)
+                {
+                    continue;
+                }
+                let Some(location) = self.location(file, &definition.span)
+                else {
+                    continue;
+                };
+                symbols.push(SymbolInformation {
+                    name: definition.name.clone(),
+                    kind: self.symbol_kind(file, definition),
+                    tags: None,
+                    deprecated: None,
+                    location,
+                    container_name: file
+                        .file_name()
+                        .map(|name| name.to_string_lossy().into_owned())
+                });
+            }
+        }
+        Some(WorkspaceSymbolResponse::Flat(symbols))
+    }
+
+    fn handle_request(&self, request: Request) -> Response {
+        fn respond<R: Serialize>(request: Request, result: R) -> Response {
+            Response::new_ok(request.id, result)
+        }
+        fn params<P: DeserializeOwned>(request: &Request) -> Option<P> {
+            serde_json::from_value(request.params.clone()).ok()
+        }
+        match request.method.as_str() {
+            HoverRequest::METHOD => {
+                let result = params(&request).and_then(|p| self.hover(p));
+                respond(request, result)
+            }
+            GotoDefinition::METHOD => {
+                let result = params(&request).and_then(|p| self.definition(p));
+                respond(request, result)
+            }
+            References::METHOD => {
+                let result = params(&request).and_then(|p| self.references(p));
+                respond(request, result)
+            }
+            DocumentSymbolRequest::METHOD => {
+                let result =
+                    params(&request).and_then(|p| self.document_symbol(p));
+                respond(request, result)
+            }
+            WorkspaceSymbolRequest::METHOD => {
+                let result =
+                    params(&request).and_then(|p| self.workspace_symbol(p));
+                respond(request, result)
+            }
+            _ => Response::new_err(
+                request.id,
+                lsp_server::ErrorCode::MethodNotFound as i32,
+                format!("unsupported request `{}`", request.method)
+            )
+        }
+    }
+
+    fn handle_notification(&mut self, notification: Notification) {
+        match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let Ok(params) = serde_json::from_value::<
+                    lsp_types::DidOpenTextDocumentParams
+                >(notification.params) else {
+                    return;
+                };
+                let Some(file) = path(&params.text_document.uri) else {
+                    return;
+                };
+                self.open.insert(file.clone());
+                self.documents.insert(
+                    file.clone(),
+                    Document::new(params.text_document.text)
+                );
+                if self.project.files().contains_key(&file) {
+                    self.reparse(&file);
+                }
+            }
+            DidChangeTextDocument::METHOD => {
+                let Ok(params) = serde_json::from_value::<
+                    lsp_types::DidChangeTextDocumentParams
+                >(notification.params) else {
+                    return;
+                };
+                let Some(file) = path(&params.text_document.uri) else {
+                    return;
+                };
+                // the server asks for full-text synchronization
+                let Some(change) = params.content_changes.into_iter().last()
+                else {
+                    return;
+                };
+                self.documents
+                    .insert(file.clone(), Document::new(change.text));
+                if self.project.files().contains_key(&file) {
+                    self.reparse(&file);
+                }
+            }
+            DidCloseTextDocument::METHOD => {
+                let Ok(params) = serde_json::from_value::<
+                    lsp_types::DidCloseTextDocumentParams
+                >(notification.params) else {
+                    return;
+                };
+                let Some(file) = path(&params.text_document.uri) else {
+                    return;
+                };
+                self.open.remove(&file);
+                if self.project.files().contains_key(&file) {
+                    self.load(&file);
+                    self.reparse(&file);
+                } else {
+                    self.documents.remove(&file);
+                }
+            }
+            DidChangeWatchedFiles::METHOD => self.rediscover(),
+            _ => {}
+        }
+    }
+
+    /// Picks up files created, changed, or deleted outside the editor.
+    fn rediscover(&mut self) {
+        let current = match self.generator.discover() {
+            Ok(current) => current,
+            Err(error) => {
+                eprintln!("{}", error);
+                return;
+            }
+        };
+        let known = self
+            .project
+            .files()
+            .keys()
+            .cloned()
+            .collect::<BTreeSet<_>>();
+        for file in known.difference(&current) {
+            self.documents.remove(file);
+            self.project.update([(file.clone(), None)]);
+        }
+        for file in &current {
+            if self.open.contains(file) {
+                continue;
+            }
+            let text = fs::read_to_string(file).ok();
+            let unchanged = known.contains(file)
+                && text
+                    .as_ref()
+                    .zip(self.documents.get(file))
+                    .is_some_and(|(text, document)| *text == document.text);
+            if !unchanged {
+                self.load(file);
+                self.reparse(file);
+            }
+        }
+    }
+}
+
+/// The declarations of `name` in `document`, which is the text of `file`.
+fn in_file<'d>(
+    file: &'d Path, document: &'d Document, name: &str
+) -> Vec<(&'d Path, &'d Definition)> {
+    document
+        .definitions
+        .iter()
+        .filter(|definition| definition.name == name)
+        .map(|definition| (file, definition))
+        .collect()
+}
+
+fn markdown(lines: Vec<String>) -> Hover {
+    Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: lines.join("\n\n")
+        }),
+        range: None
+    }
+}
+
+/// Serves the project described by `generator` over stdio until the client
+/// shuts the server down. Input paths should be absolute so that they match
+/// the paths in the client's URIs.
+pub fn run(generator: &Generator) -> Result<(), LspError> {
+    let (connection, io_threads) = Connection::stdio();
+    serve(generator, &connection)?;
+    drop(connection);
+    io_threads.join().map_err(LspError::Io)
+}
+
+/// Serves over an established `connection`, e.g., an in-memory one.
+pub fn serve(
+    generator: &Generator, connection: &Connection
+) -> Result<(), LspError> {
+    let capabilities = ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(
+            TextDocumentSyncKind::FULL
+        )),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        definition_provider: Some(OneOf::Left(true)),
+        references_provider: Some(OneOf::Left(true)),
+        document_symbol_provider: Some(OneOf::Left(true)),
+        workspace_symbol_provider: Some(OneOf::Left(true)),
+        ..Default::default()
+    };
+    let params = connection.initialize(
+        serde_json::to_value(capabilities).expect("capabilities serialize")
+    )?;
+    let mut server = Server::new(generator)?;
+
+    // watching files is only registered dynamically
+    let can_watch = serde_json::from_value::<InitializeParams>(params)
+        .ok()
+        .and_then(|params| params.capabilities.workspace)
+        .and_then(|workspace| workspace.did_change_watched_files)
+        .and_then(|watched_files| watched_files.dynamic_registration)
+        .unwrap_or(false);
+    if can_watch {
+        let options = DidChangeWatchedFilesRegistrationOptions {
+            watchers: vec![FileSystemWatcher {
+                glob_pattern: GlobPattern::String("**/*.{asm,nasm}".into()),
+                kind: None
+            }]
+        };
+        let registration = RegistrationParams {
+            registrations: vec![Registration {
+                id: DidChangeWatchedFiles::METHOD.into(),
+                method: DidChangeWatchedFiles::METHOD.into(),
+                register_options: Some(
+                    serde_json::to_value(options).expect("options serialize")
+                )
+            }]
+        };
+        let request = Request::new(
+            RequestId::from(RegisterCapability::METHOD.to_string()),
+            RegisterCapability::METHOD.into(),
+            registration
+        );
+        connection
+            .sender
+            .send(Message::Request(request))
+            .map_err(|_| LspError::Disconnected)?;
+    }
+
+    for message in &connection.receiver {
+        match message {
+            Message::Request(request) => {
+                if connection.handle_shutdown(&request)? {
+                    return Ok(());
+                }
+                let response = server.handle_request(request);
+                connection
+                    .sender
+                    .send(Message::Response(response))
+                    .map_err(|_| LspError::Disconnected)?;
+            }
+            Message::Notification(notification) => {
+                server.handle_notification(notification)
+            }
+            Message::Response(_) => {}
+        }
+    }
+    Err(LspError::Disconnected)
+}
@@ -1,80 +1,270 @@
 // Copyright (C) 2024 Ethan Uppal. All rights reserved.
 
 use std::{
-    collections::HashMap,
-    ffi, fs,
-    path::{Path, PathBuf}
+    collections::{BTreeSet, HashSet},
+    fs, mem,
+    path::Path,
+    sync::mpsc,
+    time::Duration
 };
 
 use asmdoc::{
-    assembly_file::AssemblyFile, assembly_project::AssemblyProject, cli::CLI,
-    docs::Markdown, syntax
+    cli::{Command, CLI},
+    config::{Config, Format},
+    coverage::CoverageReport,
+    generator::{write_atomically, Generated, Generator},
+    lint, lsp,
+    serve::PreviewServer,
+    verify::{self, ObjectSymbols}
 };
-use walkdir::WalkDir;
+use notify::{RecursiveMode, Watcher};
 
-fn can_parse(path: &Path) -> bool {
-    path.is_file()
-        && ["nasm", "asm"].contains(
-            &path.extension().and_then(ffi::OsStr::to_str).unwrap_or("")
-        )
-}
+/// How long to wait for further changes before regenerating, so that a
+/// burst of writes (e.g., an editor saving) causes one regeneration.
+const DEBOUNCE: Duration = Duration::from_millis(100);
 
-fn parse_file(
-    store: &mut HashMap<PathBuf, AssemblyFile>, path: &Path
+/// Regenerates documentation whenever the input files change, re-parsing
+/// only changed files.
+fn watch(
+    generator: &Generator, generated: Generated, on_update: impl Fn()
 ) -> anyhow::Result<()> {
-    let source = fs::read(path)?;
-    let source = String::from_utf8(source)?; // and_then won't work
-    store.insert(
-        path.to_owned(),
-        AssemblyFile::parse::<syntax::NASM>(path, &source)?
-    );
+    let Generated { mut project, pages } = generated;
+    let mut pages = pages.paths;
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender)?;
+    for path in generator.input_paths() {
+        watcher.watch(path, RecursiveMode::Recursive)?;
+    }
+    eprintln!("watching for changes...");
+
+    let mut known = project.files().keys().cloned().collect::<BTreeSet<_>>();
+    // changes already applied to `project` but not yet written out
+    let mut unwritten = 0;
+    while let Ok(event) = receiver.recv() {
+        let mut touched = HashSet::new();
+        let mut event = Some(event);
+        while let Some(next) = event {
+            match next {
+                Ok(next) => {
+                    touched.extend(next.paths.into_iter().filter_map(|path| {
+                        fs::canonicalize(&path).ok().or(Some(path))
+                    }))
+                }
+                // e.g., a dropped event; the rediscovery below still applies
+                Err(error) => eprintln!("{}", error)
+            }
+            event = receiver.recv_timeout(DEBOUNCE).ok();
+        }
+
+        let current = match generator.discover() {
+            Ok(current) => current,
+            // e.g., an input directory was removed; keep watching for it
+            Err(error) => {
+                eprintln!("{}", error);
+                continue;
+            }
+        };
+        let mut changes = Vec::new();
+        for file in known.difference(&current) {
+            changes.push((file.clone(), None));
+        }
+        for file in &current {
+            let is_touched = fs::canonicalize(file)
+                .is_ok_and(|file| touched.contains(&file));
+            if known.contains(file) && !is_touched {
+                continue;
+            }
+            match generator.parse(file) {
+                Ok(asm) => changes.push((file.clone(), Some(asm))),
+                // keep the previous version until the file parses again
+                Err(error) => eprintln!("{}", error)
+            }
+        }
+        if changes.is_empty() && unwritten == 0 {
+            continue;
+        }
+        known.extend(changes.iter().map(|(file, _)| file.clone()));
+        known.retain(|file| current.contains(file));
+
+        unwritten += changes.len();
+        project.update(changes);
+        let new_pages = match generator.write(&project) {
+            Ok(new_pages) => new_pages,
+            // retried on the next change
+            Err(error) => {
+                eprintln!("{}", error);
+                continue;
+            }
+        };
+        let changed = mem::take(&mut unwritten);
+        for stale in pages.difference(&new_pages.paths) {
+            let _ = fs::remove_file(generator.output_directory().join(stale));
+        }
+        pages = new_pages.paths;
+        on_update();
+        eprintln!(
+            "{} file{} changed, {} page{} rewritten",
+            changed,
+            if changed == 1 { "" } else { "s" },
+            new_pages.written,
+            if new_pages.written == 1 { "" } else { "s" }
+        );
+    }
     Ok(())
 }
 
 fn main() -> anyhow::Result<()> {
     let args = CLI::parse();
-    assert!(
-        args.out_dir.is_dir() || !args.out_dir.exists(),
-        "argument passed '-o' was not a directory"
-    );
-
-    let mut files = HashMap::new();
-    for path in &args.paths {
-        if can_parse(path) {
-            parse_file(&mut files, path)?;
-        } else if path.is_dir() {
-            for file in WalkDir::new(path).into_iter().flatten() {
-                if can_parse(file.path()) {
-                    parse_file(&mut files, file.path())?;
-                }
-            }
+    let mut config = match &args.config {
+        Some(path) => Config::load(path)?,
+        None => Config::find(Path::new("."))?.unwrap_or_default()
+    };
+
+    // command-line flags override the configuration file
+    if let Some(out_dir) = &args.out_dir {
+        config.output.directory = Some(out_dir.clone());
+    }
+    config.output.flat |= args.flat;
+    if !args.format.is_empty() {
+        config.output.formats = args.format.clone();
+    }
+    let serve = match &args.command {
+        Some(Command::Serve(serve)) => Some(serve),
+        _ => None
+    };
+    if serve.is_some() && !config.output.formats.contains(&Format::Html) {
+        if config.output.formats.is_empty() {
+            config.output.formats.push(Format::Markdown);
+        }
+        config.output.formats.push(Format::Html);
+    }
+    match &args.command {
+        Some(Command::Check(check)) if !check.paths.is_empty() => {
+            config.input.paths = check.paths.clone();
+        }
+        Some(Command::Serve(serve)) if !serve.paths.is_empty() => {
+            config.input.paths = serve.paths.clone();
         }
+        Some(Command::Lsp(lsp)) if !lsp.paths.is_empty() => {
+            config.input.paths = lsp.paths.clone();
+        }
+        Some(Command::Verify(verify)) if !verify.paths.is_empty() => {
+            config.input.paths = verify.paths.clone();
+        }
+        _ if !args.paths.is_empty() => config.input.paths = args.paths.clone(),
+        _ => {}
+    }
+    config.input.include.extend(args.include.iter().cloned());
+    config.input.exclude.extend(args.exclude.iter().cloned());
+    if args.no_gitignore {
+        config.input.gitignore = Some(false);
+    }
+    config.input.follow_symlinks |= args.follow_symlinks;
+    if let Some(directory) = &args.cache_dir {
+        config.input.cache_directory = Some(directory.clone());
+    }
+    if let Some(layout) = &args.layout {
+        config.input.layout = Some(layout.clone());
     }
 
-    // let mut output_toml = toml::Table::new();
-    // for (file, asm) in store {
-    //     output_toml.insert(
-    //         file.to_string_lossy().to_string(),
-    //         toml::Value::try_from(&asm).unwrap()
-    //     );
-    // }
-    // println!("{}", toml::to_string_pretty(&output_toml).unwrap());
-
-    let project = AssemblyProject::build_from(files);
-    let docs = project.generate_docs();
-    if fs::read_dir(&args.out_dir).is_err() {
-        fs::create_dir(&args.out_dir)?;
+    if let Some(Command::Lsp(_)) = args.command {
+        // clients identify files by absolute path
+        if config.input.paths.is_empty() {
+            config.input.paths.push(".".into());
+        }
+        for path in &mut config.input.paths {
+            *path = fs::canonicalize(&*path)?;
+        }
+        config.root = fs::canonicalize(match config.root.as_os_str() {
+            root if root.is_empty() => Path::new("."),
+            _ => &config.root
+        })?;
+        let generator =
+            Generator::from_config(&config).jobs(args.jobs.unwrap_or(0));
+        return Ok(lsp::run(&generator)?);
     }
-    let mut file_map = HashMap::new();
-    for (file, _) in &docs {
-        let output_relative_path =
-            PathBuf::from(file.with_extension("md").file_name().unwrap());
-        file_map.insert(file.clone(), output_relative_path);
+
+    let generator =
+        Generator::from_config(&config).jobs(args.jobs.unwrap_or(0));
+    let project = generator.project()?;
+
+    if let Some(Command::Check(_)) = args.command {
+        let diagnostics = lint::check(&project);
+        for diagnostic in &diagnostics {
+            eprintln!("{}", diagnostic);
+        }
+        if !diagnostics.is_empty() {
+            anyhow::bail!(
+                "found {} problem{}",
+                diagnostics.len(),
+                if diagnostics.len() == 1 { "" } else { "s" }
+            );
+        }
+        return Ok(());
     }
-    for (file, docs) in &docs {
-        let mut output_path = PathBuf::from(&args.out_dir);
-        output_path.push(file_map.get(file).unwrap());
-        fs::write(output_path, docs.to::<Markdown>(&file_map))?;
+
+    if let Some(Command::Verify(verify)) = &args.command {
+        if verify.object.is_empty() {
+            anyhow::bail!("no objects to verify against; pass --object <path>");
+        }
+        let objects = verify
+            .object
+            .iter()
+            .map(|path| ObjectSymbols::read(path))
+            .collect::<Result<Vec<_>, _>>()?;
+        let diagnostics = verify::verify(&project, &objects);
+        for diagnostic in &diagnostics {
+            eprintln!("{}", diagnostic);
+        }
+        if !diagnostics.is_empty() {
+            anyhow::bail!(
+                "found {} discrepanc{}",
+                diagnostics.len(),
+                if diagnostics.len() == 1 { "y" } else { "ies" }
+            );
+        }
+        return Ok(());
+    }
+
+    if args.coverage || args.coverage_json.is_some() {
+        let report = CoverageReport::of(&project, args.coverage_private);
+        print!("{}", report);
+        if let Some(path) = &args.coverage_json {
+            write_atomically(path, |sink| {
+                serde_json::to_writer_pretty(sink, &report)?;
+                Ok(())
+            })?;
+        }
+        if let Some(minimum) = args.min_coverage {
+            if report.percentage < minimum {
+                anyhow::bail!(
+                    "documentation coverage of {:.1}% is below the minimum of \
+                     {}%",
+                    report.percentage,
+                    minimum
+                );
+            }
+        }
+        return Ok(());
+    }
+
+    // like a linker, complain about conflicting definitions
+    for diagnostic in lint::duplicate_globals(&project) {
+        eprintln!("{}", diagnostic);
+    }
+
+    let pages = generator.write(&project)?;
+    let generated = Generated { project, pages };
+
+    if let Some(serve) = serve {
+        let server = PreviewServer::start(
+            generator.output_directory().to_path_buf(),
+            serve.port
+        )?;
+        eprintln!("serving documentation at {}", server.address());
+        watch(&generator, generated, || server.reload())?;
+    } else if args.watch {
+        watch(&generator, generated, || {})?;
     }
 
     Ok(())
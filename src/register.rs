@@ -0,0 +1,250 @@
+// Copyright (C) 2024 Ethan Uppal. All rights reserved.
+
+use std::fmt::{self, Display};
+
+use serde::{Deserialize, Serialize};
+
+/// The kind of an x86 register.
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum RegisterClass {
+    /// A general-purpose register, with its width in bits.
+    General(usize),
+    /// An SSE/AVX register (`xmm`, `ymm`, `zmm`), with its width in bits.
+    Vector(usize),
+    /// An AVX-512 opmask register (`k0`-`k7`).
+    Mask,
+    /// A control register (`cr0`-`cr15`).
+    Control,
+    /// A debug register (`dr0`-`dr15`).
+    Debug,
+    Segment,
+    /// An x87 stack register (`st0`-`st7`).
+    X87,
+    /// An MMX register (`mm0`-`mm7`).
+    MMX,
+    /// An MPX bound register (`bnd0`-`bnd3`).
+    Bound,
+    /// The instruction pointer (`rip`, `eip`).
+    InstructionPointer
+}
+
+/// A register operand.
+#[derive(PartialEq, Eq, Hash, Clone, Debug, Serialize, Deserialize)]
+pub struct Register {
+    /// The lowercase register name, e.g., `eax`.
+    pub name: String,
+    pub class: RegisterClass
+}
+
+/// The legacy general-purpose registers, by 64-bit name, with their 32-bit,
+/// 16-bit, and low 8-bit names.
+const LEGACY_GENERAL: [(&str, &str, &str, &str); 8] = [
+    ("rax", "eax", "ax", "al"),
+    ("rbx", "ebx", "bx", "bl"),
+    ("rcx", "ecx", "cx", "cl"),
+    ("rdx", "edx", "dx", "dl"),
+    ("rsi", "esi", "si", "sil"),
+    ("rdi", "edi", "di", "dil"),
+    ("rbp", "ebp", "bp", "bpl"),
+    ("rsp", "esp", "sp", "spl")
+];
+
+/// The legacy high 8-bit registers and their 64-bit parents.
+const LEGACY_HIGH: [(&str, &str); 4] =
+    [("ah", "rax"), ("bh", "rbx"), ("ch", "rcx"), ("dh", "rdx")];
+
+const SEGMENT: [&str; 6] = ["cs", "ds", "es", "fs", "gs", "ss"];
+
+/// The decimal index following `prefix` in `name`, if it lies in `range`.
+fn indexed(
+    name: &str, prefix: &str, range: std::ops::RangeInclusive<usize>
+) -> Option<usize> {
+    let digits = name.strip_prefix(prefix)?;
+    if digits.is_empty()
+        || (digits.len() > 1 && digits.starts_with('0'))
+        || !digits.chars().all(|c| c.is_ascii_digit())
+    {
+        return None;
+    }
+    let index = digits.parse().ok()?;
+    range.contains(&index).then_some(index)
+}
+
+impl Register {
+    /// Classifies `name` (in any case) as a register, or returns `None` if it
+    /// does not name one.
+    pub fn parse(name: &str) -> Option<Self> {
+        let name = name.to_ascii_lowercase();
+        let class = Self::classify(&name)?;
+        Some(Self { name, class })
+    }
+
+    fn classify(name: &str) -> Option<RegisterClass> {
+        use RegisterClass::*;
+
+        for (r64, r32, r16, r8) in LEGACY_GENERAL {
+            for (alias, width) in [(r64, 64), (r32, 32), (r16, 16), (r8, 8)] {
+                if name == alias {
+                    return Some(General(width));
+                }
+            }
+        }
+        if LEGACY_HIGH.iter().any(|(high, _)| *high == name) {
+            return Some(General(8));
+        }
+        if SEGMENT.contains(&name) {
+            return Some(Segment);
+        }
+        if name == "rip" || name == "eip" {
+            return Some(InstructionPointer);
+        }
+
+        if let Some(rest) = name.strip_prefix('r') {
+            let (digits, width) = match rest.char_indices().last()? {
+                (i, 'd') => (&rest[..i], 32),
+                (i, 'w') => (&rest[..i], 16),
+                (i, 'b' | 'l') => (&rest[..i], 8),
+                _ => (rest, 64)
+            };
+            if indexed(digits, "", 8..=15).is_some() {
+                return Some(General(width));
+            }
+        }
+
+        for (prefix, width) in [("xmm", 128), ("ymm", 256), ("zmm", 512)] {
+            if indexed(name, prefix, 0..=31).is_some() {
+                return Some(Vector(width));
+            }
+        }
+
+        [
+            ("k", 0..=7, Mask),
+            ("cr", 0..=15, Control),
+            ("dr", 0..=15, Debug),
+            ("st", 0..=7, X87),
+            ("mm", 0..=7, MMX),
+            ("bnd", 0..=3, Bound)
+        ]
+        .into_iter()
+        .find(|(prefix, range, _)| {
+            indexed(name, prefix, range.clone()).is_some()
+        })
+        .map(|(_, _, class)| class)
+    }
+
+    /// The full register that this register is part of, e.g., `rax` for `al`
+    /// or `zmm3` for `xmm3`. Writing to a register may affect its full
+    /// register, so analyses should compare full registers.
+    pub fn full(&self) -> Register {
+        let name = match self.class {
+            RegisterClass::General(_) => LEGACY_GENERAL
+                .iter()
+                .find(|(r64, r32, r16, r8)| {
+                    [*r64, *r32, *r16, *r8].contains(&self.name.as_str())
+                })
+                .map(|(r64, ..)| r64.to_string())
+                .or_else(|| {
+                    LEGACY_HIGH
+                        .iter()
+                        .find(|(high, _)| *high == self.name)
+                        .map(|(_, r64)| r64.to_string())
+                })
+                .unwrap_or_else(|| {
+                    self.name.trim_end_matches(['d', 'w', 'b', 'l']).to_string()
+                }),
+            RegisterClass::Vector(_) => format!("zmm{}", &self.name[3..]),
+            _ => self.name.clone()
+        };
+        let class = match self.class {
+            RegisterClass::General(_) => RegisterClass::General(64),
+            RegisterClass::Vector(_) => RegisterClass::Vector(512),
+            class => class
+        };
+        Register { name, class }
+    }
+}
+
+impl Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.name.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Register, RegisterClass::*};
+
+    #[test]
+    fn registers_are_classified() {
+        for (name, class) in [
+            ("rax", General(64)),
+            ("eax", General(32)),
+            ("ax", General(16)),
+            ("al", General(8)),
+            ("ah", General(8)),
+            ("dh", General(8)),
+            ("sil", General(8)),
+            ("spl", General(8)),
+            ("r8", General(64)),
+            ("r9d", General(32)),
+            ("r10w", General(16)),
+            ("r11b", General(8)),
+            ("r15l", General(8)),
+            ("xmm0", Vector(128)),
+            ("ymm15", Vector(256)),
+            ("zmm31", Vector(512)),
+            ("k0", Mask),
+            ("k7", Mask),
+            ("cr0", Control),
+            ("cr15", Control),
+            ("dr7", Debug),
+            ("cs", Segment),
+            ("gs", Segment),
+            ("st0", X87),
+            ("mm7", MMX),
+            ("bnd3", Bound),
+            ("rip", InstructionPointer),
+            ("EIP", InstructionPointer)
+        ] {
+            let register = Register::parse(name);
+            assert_eq!(register.map(|r| r.class), Some(class), "{}", name);
+        }
+    }
+
+    #[test]
+    fn non_registers_are_rejected() {
+        for name in [
+            "", "r", "r7", "r16", "r08", "r8x", "xmm", "xmm32", "zmm32",
+            "zmm01", "k8", "cr16", "dr16", "st8", "mm8", "bnd4", "rdxw",
+            "label"
+        ] {
+            assert_eq!(Register::parse(name), None, "{}", name);
+        }
+    }
+
+    #[test]
+    fn full_registers() {
+        for (name, full, class) in [
+            ("rax", "rax", General(64)),
+            ("EAX", "rax", General(64)),
+            ("ah", "rax", General(64)),
+            ("bl", "rbx", General(64)),
+            ("sil", "rsi", General(64)),
+            ("bp", "rbp", General(64)),
+            ("r8", "r8", General(64)),
+            ("r9d", "r9", General(64)),
+            ("r10w", "r10", General(64)),
+            ("r11b", "r11", General(64)),
+            ("r12l", "r12", General(64)),
+            ("xmm3", "zmm3", Vector(512)),
+            ("ymm17", "zmm17", Vector(512)),
+            ("zmm31", "zmm31", Vector(512)),
+            ("k1", "k1", Mask),
+            ("fs", "fs", Segment)
+        ] {
+            let register = Register::parse(name).unwrap().full();
+            assert_eq!(register.name, full, "{}", name);
+            assert_eq!(register.class, class, "{}", name);
+        }
+    }
+}
@@ -0,0 +1,450 @@
+// Copyright (C) 2024 Ethan Uppal. All rights reserved.
+
+use std::fmt::{self, Display};
+
+use crate::{
+    assembly_file::{DocComment, Instruction, Operand},
+    register::{Register, RegisterClass}
+};
+
+/// Registers a System V callee must restore before returning.
+const CALLEE_SAVED: [&str; 6] = ["rbx", "rbp", "r12", "r13", "r14", "r15"];
+
+/// Registers a System V call may overwrite. Vector registers are also
+/// caller-saved but are omitted to keep clobber lists readable.
+const CALLER_SAVED: [&str; 9] =
+    ["rax", "rcx", "rdx", "rsi", "rdi", "r8", "r9", "r10", "r11"];
+
+/// Registers read and written by the Linux `syscall` convention.
+const SYSCALL_READ: [&str; 7] = ["rax", "rdi", "rsi", "rdx", "r10", "r8", "r9"];
+const SYSCALL_WRITTEN: [&str; 3] = ["rax", "rcx", "r11"];
+
+fn register(name: &str) -> Register {
+    Register::parse(name).expect("invalid register name")
+}
+
+/// Adds `register` (as its full register) to `registers` unless present.
+fn insert(registers: &mut Vec<Register>, register: &Register) {
+    let register = register.full();
+    if !registers.contains(&register) {
+        registers.push(register);
+    }
+}
+
+/// The registers an instruction reads and writes, as full registers.
+#[derive(Debug, Default)]
+pub struct Effects {
+    pub read: Vec<Register>,
+    pub written: Vec<Register>
+}
+
+impl Effects {
+    fn read(&mut self, name: &str) {
+        insert(&mut self.read, &register(name));
+    }
+
+    fn write(&mut self, name: &str) {
+        insert(&mut self.written, &register(name));
+    }
+
+    fn read_write(&mut self, name: &str) {
+        self.read(name);
+        self.write(name);
+    }
+
+    fn operand(&mut self, operand: &Operand, read: bool, write: bool) {
+        match operand {
+            Operand::Register(register) => {
+                if read {
+                    insert(&mut self.read, register);
+                }
+                if write {
+                    insert(&mut self.written, register);
+                }
+            }
+            Operand::Memory(registers) => {
+                for register in registers {
+                    insert(&mut self.read, register);
+                }
+            }
+            Operand::Other(_) => {}
+        }
+    }
+
+    /// Computes the effects of `instruction`. Unknown instructions are
+    /// assumed to read all operands and also write the first.
+    pub fn of(instruction: &Instruction) -> Self {
+        let mut effects = Self::default();
+        let mnemonic = instruction.mnemonic.as_str();
+        let operands = instruction.operands.as_slice();
+
+        let is_string_op = operands.is_empty()
+            && ["movs", "stos", "lods", "scas", "cmps", "ins", "outs"]
+                .iter()
+                .any(|op| mnemonic.starts_with(op));
+        let is_zeroing = matches!(
+            mnemonic,
+            "xor" | "sub" | "pxor" | "xorps" | "xorpd" | "vpxor" | "vxorps"
+        ) && matches!(
+            operands,
+            [Operand::Register(a), Operand::Register(b), ..] if a == b
+        );
+
+        // whether the explicit destination is read and written; sources are
+        // read, and exchanges also write them
+        let (dest_read, dest_written) = match mnemonic {
+            _ if is_zeroing => (false, true),
+            "cmp" | "test" | "bt" | "push" | "jmp" | "call" | "ptest"
+            | "comiss" | "comisd" | "ucomiss" | "ucomisd" | "out" => {
+                (true, false)
+            }
+            _ if mnemonic.starts_with('j') || mnemonic.starts_with("loop") => {
+                (true, false)
+            }
+            "xchg" | "xadd" | "cmpxchg" => (true, true),
+            "lea" | "pop" | "in" | "movzx" | "movsx" | "movsxd" => {
+                (false, true)
+            }
+            _ if mnemonic.starts_with("cmov") => (true, true),
+            _ if mnemonic.starts_with("mov")
+                || mnemonic.starts_with("vmov")
+                || mnemonic.starts_with("cvt")
+                || mnemonic.starts_with("vcvt")
+                || mnemonic.starts_with("set") =>
+            {
+                (false, true)
+            }
+            // VEX-encoded three-operand forms do not read the destination
+            _ if mnemonic.starts_with('v') && operands.len() >= 3 => {
+                (false, true)
+            }
+            _ => (true, true)
+        };
+        if let Some((dest, sources)) = operands.split_first() {
+            effects.operand(dest, dest_read, dest_written);
+            let sources_written = matches!(mnemonic, "xchg" | "xadd");
+            for source in sources {
+                effects.operand(source, !is_zeroing, sources_written);
+            }
+        }
+
+        // implicit operands
+        match mnemonic {
+            "push" | "pop" | "call" | "ret" | "pushf" | "popf" | "pushfq"
+            | "popfq" | "enter" => effects.read_write("rsp"),
+            "leave" => {
+                effects.read_write("rsp");
+                effects.read_write("rbp");
+            }
+            "mul" | "div" | "idiv" => {
+                effects.read_write("rax");
+                effects.read_write("rdx");
+            }
+            "imul" if operands.len() == 1 => {
+                effects.read_write("rax");
+                effects.write("rdx");
+            }
+            "cwd" | "cdq" | "cqo" => {
+                effects.read("rax");
+                effects.write("rdx");
+            }
+            "cbw" | "cwde" | "cdqe" => effects.read_write("rax"),
+            "syscall" => {
+                SYSCALL_READ.iter().for_each(|name| effects.read(name));
+                SYSCALL_WRITTEN.iter().for_each(|name| effects.write(name));
+            }
+            "cpuid" => {
+                effects.read_write("rax");
+                effects.read_write("rcx");
+                effects.write("rbx");
+                effects.write("rdx");
+            }
+            "rdtsc" => {
+                effects.write("rax");
+                effects.write("rdx");
+            }
+            "rdtscp" => {
+                effects.write("rax");
+                effects.write("rdx");
+                effects.write("rcx");
+            }
+            "jrcxz" | "jecxz" => effects.read("rcx"),
+            _ if mnemonic.starts_with("loop") => effects.read_write("rcx"),
+            _ if is_string_op => {
+                if mnemonic.starts_with("stos") || mnemonic.starts_with("scas")
+                {
+                    effects.read("rax");
+                }
+                if mnemonic.starts_with("lods") || mnemonic.starts_with("ins") {
+                    effects.write("rax");
+                }
+                if !mnemonic.starts_with("stos") && !mnemonic.starts_with("ins")
+                {
+                    effects.read_write("rsi");
+                }
+                if !mnemonic.starts_with("lods")
+                    && !mnemonic.starts_with("outs")
+                {
+                    effects.read_write("rdi");
+                }
+            }
+            _ => {}
+        }
+        if is_string_op && instruction.prefix.is_some() {
+            effects.read_write("rcx");
+        }
+
+        effects
+    }
+}
+
+/// How a routine uses registers, tracked by full register (e.g., `rax` for
+/// `eax`).
+#[derive(Debug, Clone, Default)]
+pub struct RegisterUsage {
+    /// Registers the routine reads, in order of first use.
+    pub read: Vec<Register>,
+    /// Registers the routine writes directly, in order of first use.
+    pub written: Vec<Register>,
+    /// Registers pushed and later popped, assumed to be saved and restored.
+    pub saved: Vec<Register>,
+    /// Names of the routines this routine calls or tail-calls.
+    pub calls: Vec<String>,
+    /// Whether the routine calls through a register or memory, so may reach
+    /// any routine.
+    pub calls_indirectly: bool,
+    /// Registers whose values may differ on return, including those
+    /// overwritten by callees. Filled in by [`RegisterUsage::clobber`].
+    pub clobbered: Vec<Register>
+}
+
+impl RegisterUsage {
+    /// Analyzes the body of a routine. `is_routine` reports whether a jump
+    /// target names a routine, in which case the jump is a tail call.
+    pub fn analyze<'a>(
+        body: impl IntoIterator<Item = &'a Instruction>,
+        is_routine: impl Fn(&str) -> bool
+    ) -> Self {
+        let mut usage = Self::default();
+        let mut pushed = Vec::new();
+        let mut calls = Vec::new();
+        for instruction in body {
+            let effects = Effects::of(instruction);
+            effects.read.iter().for_each(|r| insert(&mut usage.read, r));
+            effects
+                .written
+                .iter()
+                .for_each(|r| insert(&mut usage.written, r));
+
+            match (instruction.mnemonic.as_str(), &instruction.operands[..]) {
+                ("push", [Operand::Register(register)]) => {
+                    insert(&mut pushed, register)
+                }
+                ("pop", [Operand::Register(register)])
+                    if pushed.contains(&register.full()) =>
+                {
+                    insert(&mut usage.saved, register)
+                }
+                // a local label's instructions are part of the body already
+                ("call", [Operand::Other(target)])
+                    if !target.starts_with('.') =>
+                {
+                    calls.push(target)
+                }
+                ("call", [Operand::Register(_) | Operand::Memory(_)]) => {
+                    usage.calls_indirectly = true
+                }
+                ("jmp", [Operand::Other(target)])
+                    if !target.starts_with('.') && is_routine(target) =>
+                {
+                    calls.push(target)
+                }
+                _ => {}
+            }
+        }
+        for target in calls {
+            if !usage.calls.contains(target) {
+                usage.calls.push(target.clone());
+            }
+        }
+        usage.clobber(&[]);
+        usage
+    }
+
+    /// Recomputes [`RegisterUsage::clobbered`] given the registers clobbered
+    /// by callees, returning whether it changed. Indirect calls are assumed
+    /// to clobber all caller-saved registers.
+    pub fn clobber(&mut self, by_callees: &[Register]) -> bool {
+        let by_unknown = if self.calls_indirectly {
+            Self::caller_saved()
+        } else {
+            Vec::new()
+        };
+        let mut clobbered = Vec::new();
+        for register in self.written.iter().chain(by_callees).chain(&by_unknown)
+        {
+            if !self.saved.contains(register)
+                && register.class != RegisterClass::InstructionPointer
+                && register.name != "rsp"
+            {
+                insert(&mut clobbered, register);
+            }
+        }
+        let changed = clobbered != self.clobbered;
+        self.clobbered = clobbered;
+        changed
+    }
+
+    /// The registers a call to an unknown routine may clobber.
+    pub fn caller_saved() -> Vec<Register> {
+        CALLER_SAVED.iter().map(|name| register(name)).collect()
+    }
+
+    /// Compares the inferred usage with the `@clobbers` and `@preserves` tags
+    /// of `doc`.
+    pub fn mismatches(&self, doc: Option<&DocComment>) -> Vec<UsageMismatch> {
+        let declares_clobbers =
+            doc.is_some_and(|doc| doc.tag("clobbers").next().is_some());
+        let clobbers = doc
+            .map(|doc| doc.tag_registers("clobbers"))
+            .unwrap_or_default();
+        let preserves = doc
+            .map(|doc| doc.tag_registers("preserves"))
+            .unwrap_or_default();
+
+        let mut mismatches = Vec::new();
+        for register in &self.clobbered {
+            if preserves.contains(register) {
+                mismatches
+                    .push(UsageMismatch::ClobbersPreserved(register.clone()));
+            } else if declares_clobbers {
+                if !clobbers.contains(register) {
+                    mismatches.push(UsageMismatch::UndocumentedClobber(
+                        register.clone()
+                    ));
+                }
+            } else if CALLEE_SAVED.contains(&register.name.as_str()) {
+                mismatches
+                    .push(UsageMismatch::ClobbersCalleeSaved(register.clone()));
+            }
+        }
+        mismatches
+    }
+}
+
+/// A disagreement between a routine's documentation and its inferred
+/// register usage.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UsageMismatch {
+    /// The register is listed in `@preserves` but clobbered.
+    ClobbersPreserved(Register),
+    /// The routine has `@clobbers` but it omits this clobbered register.
+    UndocumentedClobber(Register),
+    /// The routine clobbers a System V callee-saved register without
+    /// declaring it in `@clobbers`.
+    ClobbersCalleeSaved(Register)
+}
+
+impl Display for UsageMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ClobbersPreserved(register) => {
+                write!(f, "clobbers `{}` despite `@preserves`", register)
+            }
+            Self::UndocumentedClobber(register) => {
+                write!(f, "clobbers `{}`, which `@clobbers` omits", register)
+            }
+            Self::ClobbersCalleeSaved(register) => {
+                write!(f, "clobbers callee-saved `{}`", register)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{register, RegisterUsage};
+    use crate::{
+        assembly_file::{Instruction, Operand},
+        generator::Generator
+    };
+
+    fn instruction(mnemonic: &str, operands: Vec<Operand>) -> Instruction {
+        Instruction {
+            prefix: None,
+            mnemonic: mnemonic.into(),
+            operands
+        }
+    }
+
+    #[test]
+    fn indirect_calls_clobber_caller_saved_registers() {
+        let targets = [
+            // call rax
+            Operand::Register(register("rax")),
+            // call [rel table]
+            Operand::Memory(Vec::new()),
+            // call [table + rbx * 8]
+            Operand::Memory(vec![register("rbx")])
+        ];
+        for target in targets {
+            let body = [
+                instruction("push", vec![Operand::Register(register("rbx"))]),
+                instruction("call", vec![target]),
+                instruction("pop", vec![Operand::Register(register("rbx"))]),
+                instruction("ret", Vec::new())
+            ];
+            let usage = RegisterUsage::analyze(&body, |_| false);
+            assert!(usage.calls_indirectly);
+            assert!(usage.calls.is_empty());
+            assert_eq!(usage.clobbered, RegisterUsage::caller_saved());
+        }
+
+        let body = [instruction("call", vec![Operand::Other("helper".into())])];
+        let usage = RegisterUsage::analyze(&body, |_| false);
+        assert!(!usage.calls_indirectly);
+        assert_eq!(usage.calls, ["helper"]);
+        assert!(usage.clobbered.is_empty());
+    }
+
+    #[test]
+    fn calls_to_local_labels_stay_in_the_routine() {
+        let body = [
+            instruction("call", vec![Operand::Other(".helper".into())]),
+            instruction("ret", Vec::new()),
+            instruction(
+                "mov",
+                vec![
+                    Operand::Register(register("eax")),
+                    Operand::Other("1".into()),
+                ]
+            ),
+            instruction("ret", Vec::new())
+        ];
+        let usage = RegisterUsage::analyze(&body, |_| false);
+        assert!(usage.calls.is_empty());
+        assert_eq!(usage.clobbered, [register("rax")]);
+
+        let generator = Generator::new();
+        let file = Path::new("f.nasm");
+        let source = "section .text
+global f
+;; Sets rax.
+;; @clobbers rax
+f:
+    call .helper
+    ret
+.helper:
+    mov eax, 1
+    ret
+";
+        let asm = generator.parse_source(file, source).unwrap();
+        let project = generator.build([(file.to_path_buf(), asm)]);
+        let symbol = project.symbol(file, "f").unwrap();
+        let usage = symbol.registers.as_ref().unwrap();
+        assert_eq!(usage.clobbered, [register("rax")]);
+        assert!(usage.mismatches(symbol.doc.as_ref()).is_empty());
+    }
+}
@@ -0,0 +1,152 @@
+// Copyright (C) 2024 Ethan Uppal. All rights reserved.
+
+//! A small HTTP server for previewing generated documentation.
+
+use std::{
+    fs, io,
+    path::{Component, Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc
+    },
+    thread
+};
+
+use tiny_http::{Header, Request, Response, Server};
+
+/// Path polled by served pages to learn when to reload.
+const VERSION_PATH: &str = "/__asmdoc/version";
+
+/// Script injected into served HTML pages, which reloads the page once the
+/// documentation is regenerated.
+const LIVE_RELOAD: &str = r#"<script>
+(function () {
+  var version = null;
+  setInterval(function () {
+    fetch("/__asmdoc/version")
+      .then(function (response) { return response.text(); })
+      .then(function (latest) {
+        if (version !== null && latest !== version) location.reload();
+        version = latest;
+      })
+      .catch(function () {});
+  }, 1000);
+})();
+</script>
+"#;
+
+/// Serves a directory on localhost, telling open pages to reload after each
+/// call to [`PreviewServer::reload`].
+pub struct PreviewServer {
+    address: String,
+    version: Arc<AtomicU64>
+}
+
+impl PreviewServer {
+    /// Starts serving `root` on `port` in a background thread.
+    pub fn start(root: PathBuf, port: u16) -> io::Result<Self> {
+        let server =
+            Server::http(("127.0.0.1", port)).map_err(io::Error::other)?;
+        let address = format!("http://{}", server.server_addr());
+        let version = Arc::new(AtomicU64::new(0));
+        let server_version = version.clone();
+        thread::spawn(move || {
+            for request in server.incoming_requests() {
+                let version = server_version.load(Ordering::SeqCst);
+                // a client hanging up is not our problem
+                let _ = respond(&root, version, request);
+            }
+        });
+        Ok(Self { address, version })
+    }
+
+    /// The URL of the server.
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    /// Tells open pages that the documentation changed.
+    pub fn reload(&self) {
+        self.version.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+/// Decodes `%XX` escapes in a URL path.
+fn percent_decode(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| path.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// The file under `root` that `url` names, if it does not escape `root`.
+fn resolve(root: &Path, url: &str) -> Option<PathBuf> {
+    let path = url.split(['?', '#']).next().unwrap_or("");
+    let path = percent_decode(path);
+    let mut file = root.to_path_buf();
+    for component in Path::new(path.trim_start_matches('/')).components() {
+        match component {
+            Component::Normal(part) => file.push(part),
+            Component::CurDir => {}
+            _ => return None
+        }
+    }
+    if file.is_dir() {
+        file.push("index.html");
+    }
+    Some(file)
+}
+
+fn content_type(file: &Path) -> &'static str {
+    match file.extension().and_then(|extension| extension.to_str()) {
+        Some("html") => "text/html; charset=utf-8",
+        Some("md") => "text/markdown; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("js") => "text/javascript; charset=utf-8",
+        Some("json") => "application/json",
+        _ => "application/octet-stream"
+    }
+}
+
+fn respond(root: &Path, version: u64, request: Request) -> io::Result<()> {
+    if request.url() == VERSION_PATH {
+        return request.respond(Response::from_string(version.to_string()));
+    }
+    let Some(contents) = resolve(root, request.url())
+        .and_then(|file| Some((fs::read(&file).ok()?, file)))
+    else {
+        return request
+            .respond(Response::from_string("not found").with_status_code(404));
+    };
+    let (mut contents, file) = contents;
+    let content_type = content_type(&file);
+    if content_type.starts_with("text/html") {
+        let html = String::from_utf8_lossy(&contents);
+        contents = match html.rfind("</body>") {
+            Some(end) => {
+                format!("{}{}{}", &html[..end], LIVE_RELOAD, &html[end..])
+            }
+            None => format!("{}{}", html, LIVE_RELOAD)
+        }
+        .into_bytes();
+    }
+    let header = Header::from_bytes("Content-Type", content_type)
+        .expect("invalid header");
+    request.respond(Response::from_data(contents).with_header(header))
+}
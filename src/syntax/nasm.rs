@@ -8,19 +8,26 @@ use std::{
 
 use logos::{Logos, Span};
 
-use crate::assembly_file::{
-    AssemblyFile, AssemblyItem, AssemblyMacro, AssemblySection
+use crate::{
+    assembly_file::{
+        AssemblyFile, AssemblyItem, AssemblyMacro, AssemblySection,
+        AssemblyStruct, DataLayout, DocComment, Instruction, Operand,
+        SectionAttributes
+    },
+    register::Register
 };
 
 use super::Syntax;
 
+pub mod literal;
+
 /// Grammar for NASM syntax.
 #[derive(Logos, Debug, PartialEq, Eq, Clone, Copy)]
 pub enum NASMTokenType {
     #[token("bits")]
     Bits,
 
-    #[token("section")]
+    #[regex("section|segment")]
     Section,
 
     #[token("global")]
@@ -45,19 +52,37 @@ pub enum NASMTokenType {
     Macro,
 
     // TODO: finish this
-    #[regex("mov|add|jmp|push|pop|call|ret|sub|mul|div|inc|dec|and|or|xor|not|shl|shr|cmp|test|db|dd|align|equ|lea|jne|je|imul|syscall|jz|jnz")]
+    #[regex("mov|add|jmp|push|pop|call|ret|sub|mul|div|inc|dec|and|or|xor|not|shl|shr|cmp|test|align|equ|lea|jne|je|imul|syscall|jz|jnz")]
     Mnemonic,
 
+    #[regex("db|dw|dd|dq|dt|do|dy|dz")]
+    DataDefine,
+
+    #[regex("resb|resw|resd|resq|rest|reso|resy|resz")]
+    DataReserve,
+
+    #[token("times")]
+    Times,
+
     #[token("%endmacro")]
     EndMacro,
 
+    #[token("struc")]
+    Struc,
+
+    #[token("endstruc")]
+    EndStruc,
+
     #[regex("\\$[a-zA-Z0-9_.]+")]
     MacroCall,
 
     #[regex("%[0-9]+")]
     MacroArg,
 
-    #[regex("r[0-9]+")]
+    /// Any x86 register; see [`crate::register::Register::parse`] for its
+    /// class.
+    #[regex("[re]?(ax|bx|cx|dx|si|di|bp|sp)|[abcd][lh]|(si|di|bp|sp)l|r(8|9|1[0-5])[dwbl]?", ignore(ascii_case))]
+    #[regex("[xyz]mm([0-9]|[12][0-9]|3[01])|k[0-7]|(cr|dr)([0-9]|1[0-5])|st[0-7]|mm[0-7]|bnd[0-3]|[cdefgs]s|[re]ip", ignore(ascii_case))]
     Register,
 
     #[regex("[a-zA-Z_.][a-zA-Z0-9_.$]*")]
@@ -66,10 +91,20 @@ pub enum NASMTokenType {
     #[token("$")]
     CurrentPosition,
 
-    #[regex(r"[0-9]+")]
+    /// An integer in any radix, e.g., `0x10`, `10h`, or `0b1010`. The regex
+    /// is permissive; see [`literal::parse_integer`] for validation.
+    #[regex(r"[0-9][0-9a-zA-Z_]*")]
     Number,
 
-    #[regex(r#"("([^"\\]|\\.)*")|('([^'\\]|\\.)*')"#)]
+    #[regex(r"[0-9][0-9_]*\.[0-9_]*([eE][+-]?[0-9_]+)?")]
+    #[regex(r"0[xXhH][0-9a-fA-F_]*\.[0-9a-fA-F_]*([pP][+-]?[0-9_]+)?")]
+    Float,
+
+    /// A float-to-integer operator such as `__float64__`.
+    #[regex(r"__\??float(8|16|32|64|80m|80e|128l|128h)\??__")]
+    FloatFunction,
+
+    #[regex(r#"("([^"\\]|\\.)*")|('([^'\\]|\\.)*')|(`([^`\\]|\\.)*`)"#)]
     String,
 
     // Comments
@@ -82,6 +117,9 @@ pub enum NASMTokenType {
     #[token(",")]
     Comma,
 
+    #[token("=")]
+    Equals,
+
     #[token("[")]
     LeftBracket,
 
@@ -144,21 +182,60 @@ impl<'a> From<SourceLocation<&'a Path>> for SourceLocation<PathBuf> {
     }
 }
 
+/// Byte offsets of the start of each line, for computing line and column
+/// numbers only when they are needed.
+pub struct LineIndex {
+    starts: Vec<usize>
+}
+
+impl LineIndex {
+    pub fn new(source: &str) -> Self {
+        let mut starts = vec![0];
+        starts.extend(
+            source
+                .bytes()
+                .enumerate()
+                .filter(|(_, byte)| *byte == b'\n')
+                .map(|(i, _)| i + 1)
+        );
+        Self { starts }
+    }
+
+    /// The 1-indexed line and byte column of `offset`.
+    pub fn line_col(&self, offset: usize) -> (usize, usize) {
+        let line = self.starts.partition_point(|start| *start <= offset);
+        (line, offset - self.starts[line - 1] + 1)
+    }
+
+    /// The offset at which the 1-indexed `line` starts, if it exists.
+    pub fn line_start(&self, line: usize) -> Option<usize> {
+        self.starts.get(line.checked_sub(1)?).copied()
+    }
+
+    pub fn locate<'a>(
+        &self, file: &'a Path, offset: usize
+    ) -> SourceLocation<&'a Path> {
+        let (line, col) = self.line_col(offset);
+        SourceLocation { file, line, col }
+    }
+}
+
+/// A token, which borrows its text from the source.
+#[derive(Clone, Copy, Debug)]
 pub struct NASMToken<'src> {
     pub ty: NASMTokenType,
     pub value: &'src str,
-    pub span: Span,
-    pub loc: SourceLocation<&'src Path>
+    /// Byte offset of the token in the source.
+    pub start: usize
 }
 
-impl<'src> Clone for NASMToken<'src> {
-    fn clone(&self) -> Self {
-        NASMToken {
-            ty: self.ty,
-            value: self.value,
-            span: self.span.clone(),
-            loc: self.loc.clone()
-        }
+impl NASMToken<'_> {
+    pub fn span(&self) -> Span {
+        self.start..self.end()
+    }
+
+    pub fn end(&self) -> usize {
+        self.start + self.value.len()
     }
 }
 
@@ -212,7 +289,10 @@ impl Display for NASMParseError {
                 f,
                 "{}({}:{}:{})",
                 rule,
-                loc.file.file_name().unwrap().to_string_lossy(),
+                loc.file
+                    .file_name()
+                    .unwrap_or(loc.file.as_os_str())
+                    .to_string_lossy(),
                 loc.line,
                 loc.col
             )?;
@@ -226,14 +306,27 @@ impl error::Error for NASMParseError {}
 type RuleResult = Result<(), NASMParseError>;
 
 pub struct NASM<'src> {
+    file: &'src Path,
+    source: &'src str,
+    lines: LineIndex,
     pos: usize,
     tokens: Vec<NASMToken<'src>>,
-    eof_token: NASMToken<'src>,
     asm: AssemblyFile,
     current_section: AssemblySection,
-    rule_stack: ParserTrace
+    /// The rules being parsed, with the offset at which each began.
+    rule_stack: Vec<(&'static str, usize)>,
+    /// Lines of the documentation comment preceding the current statement.
+    doc_lines: Vec<&'src str>,
+    /// The last non-local label, which local labels belong to.
+    scope: String
 }
 
+/// Instruction prefixes, which are followed by the actual mnemonic.
+const PREFIXES: [&str; 9] = [
+    "rep", "repe", "repz", "repne", "repnz", "lock", "bnd", "xacquire",
+    "xrelease"
+];
+
 macro_rules! rules {
     ($($vis:vis rule $name:ident(&mut $self:ident $(, $arg:ident: $arg_ty:ty)* $(,)?) -> RuleResult
         $body:block
@@ -245,7 +338,7 @@ macro_rules! rules {
                         return Err($self.error(NASMParseErrorType::UnexpectedEOF));
                     }
                     $self.rule_stack.push(
-                        (stringify!($name).to_string(), $self.current().loc.into())
+                        (stringify!($name), $self.current().start)
                     );
                     $body?;
                     $self.rule_stack.pop();
@@ -261,8 +354,8 @@ impl<'src> NASM<'src> {
         self.pos >= self.tokens.len()
     }
 
-    fn current(&self) -> NASMToken<'src> {
-        self.tokens[self.pos].clone()
+    fn current(&self) -> &NASMToken<'src> {
+        &self.tokens[self.pos]
     }
 
     fn advance(&mut self) {
@@ -270,8 +363,7 @@ impl<'src> NASM<'src> {
     }
 
     fn take(&mut self) -> NASMToken<'src> {
-        // can't use current() because of the borrow checker
-        let cur = self.tokens[self.pos].clone();
+        let cur = *self.current();
         self.advance();
         cur
     }
@@ -293,17 +385,24 @@ impl<'src> NASM<'src> {
         }
     }
 
+    /// The line of `token`.
+    fn line_of(&self, token: &NASMToken) -> usize {
+        self.lines.line_col(token.start).0
+    }
+
     fn error(&self, ty: NASMParseErrorType) -> NASMParseError {
-        let mut trace = self.rule_stack.clone();
+        let locate = |offset| self.lines.locate(self.file, offset).into();
+        let mut trace = self
+            .rule_stack
+            .iter()
+            .map(|(rule, offset)| (rule.to_string(), locate(*offset)))
+            .collect::<ParserTrace>();
         if self.is_eof() {
-            trace.push((
-                "end-of-file".into(),
-                self.eof_token.loc.clone().into()
-            ));
+            trace.push(("end-of-file".into(), locate(self.source.len())));
         } else {
             trace.push((
                 format!("{:?}", self.current().ty),
-                self.current().loc.clone().into()
+                locate(self.current().start)
             ));
         }
         NASMParseError { ty, trace }
@@ -335,65 +434,444 @@ impl<'src> NASM<'src> {
     }
 
     fn current_section(&mut self) -> &mut Vec<AssemblyItem> {
-        self.asm.sections.entry(self.current_section).or_default()
+        self.asm
+            .sections
+            .entry(self.current_section.clone())
+            .or_default()
+    }
+
+    /// Consumes a run of tokens not separated by whitespace, such as
+    /// `.note.GNU-stack` or `align=16`, returning its source text.
+    fn take_word(&mut self) -> Option<&'src str> {
+        let mut word = Vec::new();
+        while !self.is_eof()
+            && !matches!(
+                self.current().ty,
+                NASMTokenType::Newline | NASMTokenType::Comment
+            )
+            && word.last().is_none_or(|last: &NASMToken| {
+                last.end() == self.current().start
+            })
+        {
+            word.push(self.take());
+        }
+        (!word.is_empty()).then(|| self.source_of(&word))
+    }
+
+    /// The source text spanned by `tokens`, which must be contiguous.
+    fn source_of(&self, tokens: &[NASMToken<'src>]) -> &'src str {
+        match (tokens.first(), tokens.last()) {
+            (Some(first), Some(last)) => &self.source[first.start..last.end()],
+            _ => ""
+        }
+    }
+
+    /// Consumes tokens up to (but excluding) the next newline, splitting them
+    /// on top-level commas and dropping comments.
+    fn take_operands(&mut self) -> Vec<Vec<NASMToken<'src>>> {
+        let mut operands = vec![Vec::new()];
+        let mut depth = 0usize;
+        while !self.is_eof() && self.current().ty != NASMTokenType::Newline {
+            let token = self.take();
+            match token.ty {
+                NASMTokenType::Comment => continue,
+                NASMTokenType::Symbol => {
+                    self.asm.references.insert(token.value.to_string());
+                }
+                NASMTokenType::Comma if depth == 0 => {
+                    operands.push(Vec::new());
+                    continue;
+                }
+                NASMTokenType::LeftParen => depth += 1,
+                NASMTokenType::RightParen => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+            operands.last_mut().unwrap().push(token);
+        }
+        operands.retain(|operand| !operand.is_empty());
+        operands
+    }
+
+    /// The static value of `tokens` if they form a single integer, character
+    /// constant, or float-to-integer conversion.
+    fn constant(tokens: &[NASMToken<'src>]) -> Option<u64> {
+        match tokens {
+            [token] => match token.ty {
+                NASMTokenType::Number => literal::parse_integer(token.value),
+                NASMTokenType::String => {
+                    literal::parse_char_constant(token.value)
+                }
+                _ => None
+            },
+            [function, left, value, right]
+                if function.ty == NASMTokenType::FloatFunction
+                    && left.ty == NASMTokenType::LeftParen
+                    && matches!(
+                        value.ty,
+                        NASMTokenType::Float | NASMTokenType::Number
+                    )
+                    && right.ty == NASMTokenType::RightParen =>
+            {
+                literal::float_bits(
+                    function.value,
+                    literal::parse_float(value.value)?
+                )
+            }
+            _ => None
+        }
+    }
+
+    /// Skips to the end of the line, recording any symbols referenced.
+    fn skip_line(&mut self) {
+        while !self.is_eof() && self.current().ty != NASMTokenType::Newline {
+            let token = self.take();
+            if token.ty == NASMTokenType::Symbol {
+                self.asm.references.insert(token.value.to_string());
+            }
+        }
+    }
+
+    /// Records the line of a label, qualifying local labels with the label
+    /// they belong to.
+    fn declare_label(&mut self, name: &str, line: usize) {
+        if name.starts_with('.') {
+            self.asm
+                .lines
+                .locals
+                .entry(format!("{}{}", self.scope, name))
+                .or_insert(line);
+        } else {
+            self.scope = name.to_string();
+            self.asm
+                .lines
+                .labels
+                .entry(name.to_string())
+                .or_insert(line);
+        }
+    }
+
+    /// Attaches the pending documentation comment, if any, to `name`.
+    fn document(&mut self, name: &str) {
+        if !self.doc_lines.is_empty() {
+            let doc = DocComment::parse(self.doc_lines.drain(..));
+            self.asm.docs.insert(name.to_string(), doc);
+        }
+    }
+
+    fn operand(&self, tokens: &[NASMToken<'src>]) -> Operand {
+        match tokens {
+            [token] if token.ty == NASMTokenType::Register => {
+                Register::parse(token.value)
+                    .map(Operand::Register)
+                    .unwrap_or_else(|| Operand::Other(token.value.to_string()))
+            }
+            _ if tokens
+                .iter()
+                .any(|token| token.ty == NASMTokenType::LeftBracket) =>
+            {
+                Operand::Memory(
+                    tokens
+                        .iter()
+                        .filter(|token| token.ty == NASMTokenType::Register)
+                        .filter_map(|token| Register::parse(token.value))
+                        .collect()
+                )
+            }
+            _ => Operand::Other(self.source_of(tokens).to_string())
+        }
+    }
+
+    /// Parses a data directive with an optional `times` prefix, through the
+    /// end of the line.
+    fn data_layout(&mut self) -> Result<DataLayout, NASMParseError> {
+        let start = self.current().start;
+        let mut times = Some(1);
+        if self.current().ty == NASMTokenType::Times {
+            self.advance();
+            let mut count = Vec::new();
+            while !self.is_eof()
+                && !matches!(
+                    self.current().ty,
+                    NASMTokenType::DataDefine
+                        | NASMTokenType::DataReserve
+                        | NASMTokenType::Newline
+                )
+            {
+                count.push(self.take());
+            }
+            times = Self::constant(&count)
+                .and_then(|count| usize::try_from(count).ok());
+        }
+
+        if self.is_eof() {
+            return Err(self.error(NASMParseErrorType::UnexpectedEOF));
+        }
+        let directive = self.take();
+        let reserved = match directive.ty {
+            NASMTokenType::DataDefine => false,
+            NASMTokenType::DataReserve => true,
+            _ => {
+                return Err(self.error(NASMParseErrorType::Unexpected {
+                    expected: NASMTokenType::DataDefine,
+                    received: Some((directive.ty, directive.value.to_string()))
+                }))
+            }
+        };
+        let name = directive.value.to_ascii_lowercase();
+        let width = match &name[name.len() - 1..] {
+            "b" => 1,
+            "w" => 2,
+            "d" => 4,
+            "q" => 8,
+            "t" => 10,
+            "o" => 16,
+            "y" => 32,
+            _ => 64
+        };
+
+        let operands = self.take_operands();
+        let count = if reserved {
+            match operands.as_slice() {
+                [operand] => Self::constant(operand)
+                    .and_then(|count| usize::try_from(count).ok()),
+                _ => None
+            }
+        } else {
+            operands.iter().try_fold(0, |count, operand| {
+                match operand.as_slice() {
+                    [string] if string.ty == NASMTokenType::String => {
+                        let length = literal::parse_string(string.value)?.len();
+                        Some(count + length.div_ceil(width))
+                    }
+                    _ => Some(count + 1)
+                }
+            })
+        }
+        .zip(times)
+        .map(|(count, times)| count * times);
+
+        let values = operands
+            .iter()
+            .map(|operand| self.source_of(operand).to_string())
+            .collect();
+        let end = operands
+            .last()
+            .and_then(|operand| operand.last())
+            .map_or(directive.end(), |token| token.end());
+        let source = self.source[start..end].to_string();
+        self.expect_newline()?;
+
+        Ok(DataLayout {
+            directive: name,
+            width,
+            count,
+            values,
+            reserved,
+            source
+        })
+    }
+
+    /// Expects an integer literal that fits in a `usize`.
+    fn expect_integer(&mut self) -> Result<usize, NASMParseError> {
+        let token = self.expect(NASMTokenType::Number)?;
+        literal::parse_integer(token.value)
+            .and_then(|value| usize::try_from(value).ok())
+            .ok_or_else(|| self.error(NASMParseErrorType::InvalidSyntax))
     }
 
     rules! {
         rule bits(&mut self) -> RuleResult {
             self.expect(NASMTokenType::Bits)?;
-            self.asm.bits = self
-                .expect(NASMTokenType::Number)?
-                .value
-                .parse::<usize>()
-                .or(Err(self.error(NASMParseErrorType::InvalidSyntax)))?;
+            self.asm.bits = self.expect_integer()?;
             Ok(())
         }
 
         rule section(&mut self) -> RuleResult {
             self.expect(NASMTokenType::Section)?;
-            let section_name = self.expect(NASMTokenType::Symbol)?.value;
-            self.current_section = match section_name.to_ascii_lowercase().as_str()
-            {
-                ".text" => Some(AssemblySection::Text),
-                ".data" => Some(AssemblySection::Data),
-                ".rodata" => Some(AssemblySection::ROData),
-                ".bss" => Some(AssemblySection::BSS),
-                _ => None
+            let name = self
+                .take_word()
+                .ok_or_else(|| self.error(NASMParseErrorType::InvalidSyntax))?;
+            let section = match name.to_ascii_lowercase().as_str() {
+                ".text" => AssemblySection::Text,
+                ".data" => AssemblySection::Data,
+                ".rodata" => AssemblySection::ROData,
+                ".bss" => AssemblySection::BSS,
+                _ => AssemblySection::Custom(name.to_string())
+            };
+
+            let mut attributes = self
+                .asm
+                .section_attributes
+                .get(&section)
+                .cloned()
+                .unwrap_or_else(|| SectionAttributes::default_for(&section));
+            while let Some(attribute) = self.take_word() {
+                let attribute = attribute.to_ascii_lowercase();
+                match attribute.split_once('=') {
+                    Some(("align", align)) => {
+                        let align = literal::parse_integer(align)
+                            .and_then(|align| usize::try_from(align).ok())
+                            .ok_or_else(|| {
+                                self.error(NASMParseErrorType::InvalidSyntax)
+                            })?;
+                        attributes.align = Some(align);
+                    }
+                    // other key-value attributes (e.g., `vstart=`) do not
+                    // affect the documentation
+                    Some(_) => {}
+                    None => match attribute.as_str() {
+                        "alloc" => attributes.alloc = true,
+                        "noalloc" => attributes.alloc = false,
+                        "exec" => attributes.exec = true,
+                        "noexec" => attributes.exec = false,
+                        "write" => attributes.write = true,
+                        "nowrite" => attributes.write = false,
+                        "nobits" => attributes.nobits = true,
+                        "progbits" => attributes.nobits = false,
+                        _ => {}
+                    }
+                }
+            }
+            // skip trailing comments
+            while !self.is_eof() && self.current().ty == NASMTokenType::Comment {
+                self.advance();
             }
-            .ok_or(self.error(NASMParseErrorType::InvalidSyntax))?;
             self.expect_newline()?;
+
+            self.asm.section_attributes.insert(section.clone(), attributes);
+            self.current_section = section;
             Ok(())
         }
 
         rule label(&mut self) -> RuleResult {
-            let name = self.expect(NASMTokenType::Symbol)?.value.to_string();
+            let token = self.expect(NASMTokenType::Symbol)?;
+            let name = token.value.to_string();
             self.expect(NASMTokenType::Colon)?;
+            self.document(&name);
+            self.declare_label(&name, self.line_of(&token));
             self.current_section()
                 .push(AssemblyItem::Label(name));
             Ok(())
         }
 
+        rule data(&mut self) -> RuleResult {
+            let layout = self.data_layout()?;
+            self.current_section().push(AssemblyItem::Data(layout));
+            Ok(())
+        }
+
+        rule data_label(&mut self) -> RuleResult {
+            let token = self.expect(NASMTokenType::Symbol)?;
+            let name = token.value.to_string();
+            self.document(&name);
+            self.declare_label(&name, self.line_of(&token));
+            self.current_section()
+                .push(AssemblyItem::Label(name));
+            self.rule_data()
+        }
+
+        rule structure(&mut self) -> RuleResult {
+            self.expect(NASMTokenType::Struc)?;
+            let token = self.expect(NASMTokenType::Symbol)?;
+            let name = token.value.to_string();
+            self.expect_newline()?;
+            let line = self.line_of(&token);
+            self.asm.lines.structs.entry(name.clone()).or_insert(line);
+            self.document(&name);
+            let mut fields = Vec::new();
+            loop {
+                self.skip();
+                if self.is_eof() {
+                    return Err(self.error(NASMParseErrorType::UnexpectedEOF));
+                }
+                match self.current().ty {
+                    NASMTokenType::EndStruc => {
+                        self.advance();
+                        break;
+                    }
+                    NASMTokenType::Comment => self.advance(),
+                    NASMTokenType::Symbol => {
+                        let token = self.take();
+                        let field = token.value.to_string();
+                        let line = self.line_of(&token);
+                        self.asm
+                            .lines
+                            .fields
+                            .entry(format!("{}{}", name, field))
+                            .or_insert(line);
+                        if !self.is_eof()
+                            && self.current().ty == NASMTokenType::Colon
+                        {
+                            self.advance();
+                        }
+                        fields.push((Some(field), self.data_layout()?));
+                    }
+                    _ => fields.push((None, self.data_layout()?))
+                }
+            }
+            self.asm.structs.push(AssemblyStruct { name, fields });
+            Ok(())
+        }
+
         rule mnemonic(&mut self) -> RuleResult {
-            self.expect(NASMTokenType::Mnemonic)?;
-            while !self.is_eof() && self.current().ty != NASMTokenType::Newline {
-                self.advance();
+            let mut prefix = None;
+            let mut mnemonic = self.take().value.to_ascii_lowercase();
+            if PREFIXES.contains(&mnemonic.as_str())
+                && !self.is_eof()
+                && matches!(
+                    self.current().ty,
+                    NASMTokenType::Mnemonic | NASMTokenType::Symbol
+                )
+            {
+                prefix = Some(mnemonic);
+                mnemonic = self.take().value.to_ascii_lowercase();
             }
+            let operands = self
+                .take_operands()
+                .iter()
+                .map(|operand| self.operand(operand))
+                .collect();
+            self.expect_newline()?;
+            self.current_section().push(AssemblyItem::Mnemonic(Instruction {
+                prefix,
+                mnemonic,
+                operands
+            }));
+            Ok(())
+        }
+
+        rule constant(&mut self) -> RuleResult {
+            let token = self.expect(NASMTokenType::Symbol)?;
+            let name = token.value.to_string();
+            self.expect(NASMTokenType::Mnemonic)?;
+            self.skip_line();
             self.expect_newline()?;
+            self.document(&name);
+            let line = self.line_of(&token);
+            self.asm.lines.defines.entry(name.clone()).or_insert(line);
+            self.asm.defines.push(name);
             Ok(())
         }
 
         rule global(&mut self) -> RuleResult {
             self.expect(NASMTokenType::Global)?.value.to_string();
-            let label = self.expect(NASMTokenType::Symbol)?.value.to_string();
+            let token = self.expect(NASMTokenType::Symbol)?;
+            let label = token.value.to_string();
             self.expect_newline()?;
+            self.document(&label);
+            let line = self.line_of(&token);
+            self.asm.lines.globals.entry(label.clone()).or_insert(line);
             self.asm.globals.insert(label);
             Ok(())
         }
 
         rule extern(&mut self) -> RuleResult {
             self.expect(NASMTokenType::Extern)?;
-            let label = self.expect(NASMTokenType::Symbol)?.value.to_string();
+            let token = self.expect(NASMTokenType::Symbol)?;
+            let label = token.value.to_string();
             self.expect_newline()?;
+            let line = self.line_of(&token);
+            self.asm.lines.externs.entry(label.clone()).or_insert(line);
             self.asm.externs.push(label);
             Ok(())
         }
@@ -409,15 +887,19 @@ impl<'src> NASM<'src> {
 
         rule macro_definition(&mut self) -> RuleResult {
             self.expect(NASMTokenType::Macro)?;
-            let name = self.expect(NASMTokenType::MacroCall)?.value.to_string();
-            let arg_count = self.expect(NASMTokenType::Number)?
-                .value
-                .parse::<usize>()
-                .map_err(|_| self.error(NASMParseErrorType::InvalidSyntax))?;
+            let token = self.expect(NASMTokenType::MacroCall)?;
+            let name = token.value.to_string();
+            let line = self.line_of(&token);
+            self.asm.lines.macros.entry(name.clone()).or_insert(line);
+            let arg_count = self.expect_integer()?;
             while !self.is_eof() && self.current().ty != NASMTokenType::EndMacro {
-                self.advance();
+                let token = self.take();
+                if token.ty == NASMTokenType::Symbol {
+                    self.asm.references.insert(token.value.to_string());
+                }
             }
             self.expect(NASMTokenType::EndMacro)?;
+            self.document(&name);
             self.asm.macros.push(AssemblyMacro {
                 name, arg_count, body: Vec::new()
             });
@@ -426,9 +908,7 @@ impl<'src> NASM<'src> {
 
         rule macro_call(&mut self) -> RuleResult {
             let name = self.expect(NASMTokenType::MacroCall)?.value.to_string();
-            while !self.is_eof() && self.current().ty != NASMTokenType::Newline {
-                self.advance();
-            }
+            self.skip_line();
             self.expect_newline()?;
             self.current_section().push(AssemblyItem::MacroCall(name, Vec::new()));
             Ok(())
@@ -436,11 +916,13 @@ impl<'src> NASM<'src> {
 
         rule define(&mut self) -> RuleResult {
             self.expect(NASMTokenType::Define)?;
-            let name = self.expect(NASMTokenType::Symbol)?.value.to_string();
-            while !self.is_eof() && self.current().ty != NASMTokenType::Newline {
-                self.advance();
-            }
+            let token = self.expect(NASMTokenType::Symbol)?;
+            let name = token.value.to_string();
+            self.skip_line();
             self.expect_newline()?;
+            self.document(&name);
+            let line = self.line_of(&token);
+            self.asm.lines.defines.entry(name.clone()).or_insert(line);
             self.asm.defines.push(name);
             Ok(())
         }
@@ -453,89 +935,150 @@ impl<'src> Syntax<'src> for NASM<'src> {
     fn new_parser(
         file: &'src Path, source: &'src str
     ) -> Result<Self, Self::Error> {
+        let lines = LineIndex::new(source);
         let mut lexer = NASMTokenType::lexer(source);
         let mut tokens = Vec::new();
-        let mut line = 1;
-        let mut col = 1;
         while let Some(ty) = lexer.next() {
             let ty = ty.map_err(|_| Self::Error {
                 ty: NASMParseErrorType::InvalidInput,
                 trace: vec![(
                     "lex".into(),
-                    SourceLocation {
-                        file: file.to_path_buf(),
-                        line,
-                        col
-                    }
+                    lines.locate(file, lexer.span().start).into()
                 )]
             })?;
-
             if ty != NASMTokenType::Whitespace {
                 tokens.push(NASMToken {
                     ty,
                     value: lexer.slice(),
-                    span: lexer.span(),
-                    loc: SourceLocation { file, line, col }
+                    start: lexer.span().start
                 });
             }
-
-            if ty == NASMTokenType::Newline {
-                line += 1;
-                col = 1;
-            } else {
-                col += lexer.slice().len();
-            }
         }
-        let eof_token = NASMToken {
-            ty: NASMTokenType::EOF,
-            value: "",
-            span: Span {
-                start: source.len(),
-                end: source.len()
-            },
-            loc: SourceLocation { file, line, col }
-        };
 
         Ok(Self {
+            file,
+            source,
+            lines,
             pos: 0,
             tokens,
-            eof_token,
             asm: AssemblyFile::default(),
             current_section: AssemblySection::Text,
-            rule_stack: ParserTrace::new()
+            rule_stack: Vec::new(),
+            doc_lines: Vec::new(),
+            scope: String::new()
         })
     }
 
     fn parse(mut self) -> Result<AssemblyFile, Self::Error> {
         if !self.is_eof() {
-            self.rule_stack
-                .push(("parse".to_string(), self.current().loc.clone().into()));
+            self.rule_stack.push(("parse", self.current().start));
         }
         self.skip();
         while !self.is_eof() {
+            let is_comment = self.current().ty == NASMTokenType::Comment;
             match self.current().ty {
                 NASMTokenType::Bits => self.rule_bits(),
                 NASMTokenType::Section => self.rule_section(),
                 NASMTokenType::Symbol if self.peek_is(NASMTokenType::Colon) => {
                     self.rule_label()
                 }
-                NASMTokenType::Mnemonic => self.rule_mnemonic(),
+                NASMTokenType::Symbol
+                    if self.peek_is(NASMTokenType::DataDefine)
+                        || self.peek_is(NASMTokenType::DataReserve)
+                        || self.peek_is(NASMTokenType::Times) =>
+                {
+                    self.rule_data_label()
+                }
+                NASMTokenType::DataDefine
+                | NASMTokenType::DataReserve
+                | NASMTokenType::Times => self.rule_data(),
+                NASMTokenType::Symbol
+                    if self.tokens.get(self.pos + 1).is_some_and(|next| {
+                        next.ty == NASMTokenType::Mnemonic
+                            && next.value.eq_ignore_ascii_case("equ")
+                    }) =>
+                {
+                    self.rule_constant()
+                }
+                NASMTokenType::Mnemonic | NASMTokenType::Symbol => {
+                    self.rule_mnemonic()
+                }
                 NASMTokenType::Global => self.rule_global(),
                 NASMTokenType::Extern => self.rule_extern(),
                 NASMTokenType::Macro => self.rule_macro_definition(),
+                NASMTokenType::Struc => self.rule_structure(),
                 NASMTokenType::MacroCall => self.rule_macro_call(),
                 NASMTokenType::Comment => {
-                    // TODO:
-                    self.advance();
+                    if let Some(line) = self.take().value.strip_prefix(";;") {
+                        self.doc_lines.push(line);
+                    }
                     Ok(())
                 }
                 NASMTokenType::Include => self.rule_include(),
                 NASMTokenType::Define => self.rule_define(),
                 _ => Err(self.error(NASMParseErrorType::InvalidSyntax))
             }?;
+            if !is_comment {
+                self.doc_lines.clear();
+            }
             self.skip();
         }
 
         Ok(self.asm)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::{
+        assembly_file::{AssemblyFile, AssemblySection, SectionAttributes},
+        syntax::NASM
+    };
+
+    #[test]
+    fn sections_take_attributes() {
+        let source = "section .text align=0x10
+section .data align=8
+section .init exec align=10h
+section .table alloc write align=0b100
+section .scratch nobits write align=20q
+section .note.GNU-stack noalloc noexec nowrite progbits
+";
+        let asm =
+            AssemblyFile::parse::<NASM>(Path::new("a.nasm"), source).unwrap();
+        let attributes = |name: &str| {
+            let section = match name {
+                ".text" => AssemblySection::Text,
+                ".data" => AssemblySection::Data,
+                _ => AssemblySection::Custom(name.into())
+            };
+            asm.section_attributes[&section].clone()
+        };
+        let custom = |align, exec, write, nobits| SectionAttributes {
+            align: Some(align),
+            alloc: true,
+            exec,
+            write,
+            nobits
+        };
+        assert_eq!(attributes(".text"), custom(16, true, false, false));
+        assert_eq!(attributes(".data"), custom(8, false, true, false));
+        assert_eq!(attributes(".init"), custom(16, true, false, false));
+        assert_eq!(attributes(".table"), custom(4, false, true, false));
+        assert_eq!(attributes(".scratch"), custom(16, false, true, true));
+        assert_eq!(
+            attributes(".note.GNU-stack"),
+            SectionAttributes {
+                alloc: false,
+                ..custom(1, false, false, false)
+            }
+        );
+
+        let invalid = "section .text align=0x1g\n";
+        assert!(
+            AssemblyFile::parse::<NASM>(Path::new("a.nasm"), invalid).is_err()
+        );
+    }
+}
@@ -0,0 +1,249 @@
+// Copyright (C) 2024 Ethan Uppal. All rights reserved
+
+//! Decoding of NASM numeric, character, and string literals.
+
+/// Decodes an integer literal in any of the forms NASM accepts: a plain
+/// decimal (`200`), a radix suffix (`0c8h`, `310q`, `1100b`), or a radix
+/// prefix (`0xc8`, `0o310`, `0b1100`). Underscores are ignored. The
+/// `$`-prefixed hexadecimal form is not supported since `$name` denotes a
+/// macro call.
+pub fn parse_integer(literal: &str) -> Option<u64> {
+    let literal = literal.replace('_', "").to_ascii_lowercase();
+    if literal.is_empty() {
+        return None;
+    }
+
+    let radix_of = |c: char| match c {
+        'h' | 'x' => Some(16),
+        'd' | 't' => Some(10),
+        'q' | 'o' => Some(8),
+        'b' | 'y' => Some(2),
+        _ => None
+    };
+
+    let (init, last) = literal.split_at(literal.len() - 1);
+    if let Some(radix) = last.chars().next().and_then(radix_of) {
+        if let Ok(value) = u64::from_str_radix(init, radix) {
+            return Some(value);
+        }
+    }
+
+    if let Some(rest) = literal.strip_prefix('0') {
+        let mut chars = rest.chars();
+        if let Some(radix) = chars.next().and_then(radix_of) {
+            if let Ok(value) = u64::from_str_radix(chars.as_str(), radix) {
+                return Some(value);
+            }
+        }
+    }
+
+    literal.parse().ok()
+}
+
+/// Decodes a floating-point literal, either decimal (`1.5e3`, `1.`) or
+/// hexadecimal with a binary exponent (`0x1.8p3`).
+pub fn parse_float(literal: &str) -> Option<f64> {
+    let literal = literal.replace('_', "").to_ascii_lowercase();
+    let Some(hex) = literal
+        .strip_prefix("0x")
+        .or_else(|| literal.strip_prefix("0h"))
+    else {
+        return literal.parse().ok();
+    };
+
+    let (mantissa, exponent) = match hex.split_once('p') {
+        Some((mantissa, exponent)) => (mantissa, exponent.parse().ok()?),
+        None => (hex, 0)
+    };
+    let (whole, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    let mut value = 0.0;
+    for digit in whole.chars() {
+        value = value * 16.0 + digit.to_digit(16)? as f64;
+    }
+    let mut scale = 1.0 / 16.0;
+    for digit in fraction.chars() {
+        value += digit.to_digit(16)? as f64 * scale;
+        scale /= 16.0;
+    }
+    Some(value * 2f64.powi(exponent))
+}
+
+/// Decodes a quoted string literal, including its delimiters, into the bytes
+/// it assembles to. Escapes are only interpreted in backquoted strings.
+pub fn parse_string(literal: &str) -> Option<Vec<u8>> {
+    let quote = literal.chars().next()?;
+    let contents = literal
+        .strip_prefix(quote)?
+        .strip_suffix(quote)
+        .filter(|_| literal.len() >= 2)?;
+    if quote != '`' {
+        return Some(contents.as_bytes().to_vec());
+    }
+
+    let mut bytes = Vec::new();
+    let mut chars = contents.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buffer = [0; 4];
+            bytes.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+            continue;
+        }
+        match chars.next()? {
+            'n' => bytes.push(b'\n'),
+            't' => bytes.push(b'\t'),
+            'r' => bytes.push(b'\r'),
+            'a' => bytes.push(0x07),
+            'b' => bytes.push(0x08),
+            'f' => bytes.push(0x0c),
+            'v' => bytes.push(0x0b),
+            'e' => bytes.push(0x1b),
+            'x' => {
+                let mut value = 0;
+                for _ in 0..2 {
+                    match chars.peek().and_then(|c| c.to_digit(16)) {
+                        Some(digit) => {
+                            value = value * 16 + digit;
+                            chars.next();
+                        }
+                        None => break
+                    }
+                }
+                bytes.push(value as u8);
+            }
+            escape @ ('u' | 'U') => {
+                let width = if escape == 'u' { 4 } else { 8 };
+                let mut value = 0;
+                for _ in 0..width {
+                    value = value * 16 + chars.next()?.to_digit(16)?;
+                }
+                let mut buffer = [0; 4];
+                bytes.extend_from_slice(
+                    char::from_u32(value)?.encode_utf8(&mut buffer).as_bytes()
+                );
+            }
+            digit @ '0'..='7' => {
+                let mut value = digit.to_digit(8)?;
+                for _ in 0..2 {
+                    match chars.peek().and_then(|c| c.to_digit(8)) {
+                        Some(digit) => {
+                            value = value * 8 + digit;
+                            chars.next();
+                        }
+                        None => break
+                    }
+                }
+                bytes.push(value as u8);
+            }
+            other => {
+                let mut buffer = [0; 4];
+                bytes.extend_from_slice(
+                    other.encode_utf8(&mut buffer).as_bytes()
+                );
+            }
+        }
+    }
+    Some(bytes)
+}
+
+/// The value of a character constant such as `'A'` or `'abcd'`, which NASM
+/// stores little-endian. Returns `None` if it does not fit in 64 bits.
+pub fn parse_char_constant(literal: &str) -> Option<u64> {
+    let bytes = parse_string(literal)?;
+    if bytes.len() > 8 {
+        return None;
+    }
+    Some(
+        bytes
+            .iter()
+            .rev()
+            .fold(0, |value, byte| (value << 8) | *byte as u64)
+    )
+}
+
+/// The bit pattern of `value` as produced by the float-to-integer operator
+/// `name`, e.g., `__float64__` or `__?float32?__`, if supported.
+pub fn float_bits(name: &str, value: f64) -> Option<u64> {
+    match name.trim_matches(|c| c == '_' || c == '?') {
+        "float32" => Some((value as f32).to_bits() as u64),
+        "float64" => Some(value.to_bits()),
+        _ => None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        float_bits, parse_char_constant, parse_float, parse_integer,
+        parse_string
+    };
+
+    #[test]
+    fn integers_in_every_radix() {
+        for (literal, value) in [
+            ("200", 200),
+            ("0x10", 16),
+            ("0h10", 16),
+            ("10h", 16),
+            ("0c8h", 200),
+            ("0d99", 99),
+            ("99d", 99),
+            ("777q", 511),
+            ("0o777", 511),
+            ("1100b", 12),
+            ("0b1010", 10),
+            ("0y1010", 10),
+            ("0B1010", 10),
+            ("1_000_000", 1_000_000),
+            ("0x_dead_beef", 0xdead_beef),
+            ("1010_0101b", 0b1010_0101)
+        ] {
+            assert_eq!(parse_integer(literal), Some(value), "{}", literal);
+        }
+    }
+
+    #[test]
+    fn invalid_digits_are_rejected() {
+        for literal in ["", "_", "0x1g", "19q", "0b102", "12a", "1.5", "$10"] {
+            assert_eq!(parse_integer(literal), None, "{}", literal);
+        }
+    }
+
+    #[test]
+    fn floats() {
+        assert_eq!(parse_float("1.5e3"), Some(1500.0));
+        assert_eq!(parse_float("1."), Some(1.0));
+        assert_eq!(parse_float("1_000.25"), Some(1000.25));
+        assert_eq!(parse_float("0x1.8p3"), Some(12.0));
+        assert_eq!(parse_float("0h10"), Some(16.0));
+        assert_eq!(parse_float("1.5x"), None);
+    }
+
+    #[test]
+    fn float_conversions() {
+        assert_eq!(float_bits("__float64__", 1.5), Some(1.5f64.to_bits()));
+        assert_eq!(
+            float_bits("__?float32?__", 1.5),
+            Some(1.5f32.to_bits() as u64)
+        );
+        assert_eq!(float_bits("__float80m__", 1.5), None);
+    }
+
+    #[test]
+    fn character_constants() {
+        assert_eq!(parse_char_constant("'A'"), Some(0x41));
+        assert_eq!(parse_char_constant("\"AB\""), Some(0x4241));
+        assert_eq!(parse_char_constant("`\\n`"), Some(0x0a));
+        assert_eq!(parse_char_constant("'abcdefghi'"), None);
+    }
+
+    #[test]
+    fn only_backquoted_strings_have_escapes() {
+        assert_eq!(parse_string("'a\\n'"), Some(b"a\\n".to_vec()));
+        assert_eq!(
+            parse_string("`a\\n\\x41\\101\\u00e9`"),
+            Some("a\nAA\u{e9}".as_bytes().to_vec())
+        );
+        assert_eq!(parse_string("'unterminated"), None);
+        assert_eq!(parse_string("'"), None);
+    }
+}
@@ -0,0 +1,230 @@
+// Copyright (C) 2024 Ethan Uppal. All rights reserved.
+
+//! Tag files in the extended ctags format, for editors without LSP support.
+
+use std::{
+    fmt::{self, Display},
+    io,
+    path::{Path, PathBuf}
+};
+
+use crate::{
+    assembly_file::SectionAttributes,
+    assembly_project::AssemblyProject,
+    docs::{relative_path, Visibility}
+};
+
+/// The kind of a tagged name, written as the `kind:` field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TagKind {
+    /// A label in an executable section.
+    Function,
+    /// A label in any other section.
+    Data,
+    /// A local label, e.g., `.loop`.
+    Label,
+    Macro,
+    /// A define or `equ` constant.
+    Define,
+    Struct,
+    Field
+}
+
+impl Display for TagKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TagKind::Function => "function",
+            TagKind::Data => "data",
+            TagKind::Label => "label",
+            TagKind::Macro => "macro",
+            TagKind::Define => "define",
+            TagKind::Struct => "struct",
+            TagKind::Field => "field"
+        }
+        .fmt(f)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Tag {
+    /// The name as written where it is used, so `.loop` for a local label
+    /// but `point.x` for a structure field.
+    pub name: String,
+    pub file: PathBuf,
+    /// 1-indexed line of the declaration.
+    pub line: usize,
+    pub kind: TagKind,
+    /// The label or structure containing the name, if any.
+    pub scope: Option<(TagKind, String)>,
+    /// Whether the name is only visible in its file.
+    pub file_scoped: bool
+}
+
+/// Every declaration in `project` with a known line, sorted by name as tag
+/// files require.
+pub fn tags(project: &AssemblyProject) -> Vec<Tag> {
+    let mut tags = Vec::new();
+    for (file, asm) in project.files() {
+        let tag = |name: &str, line, kind, file_scoped| Tag {
+            name: name.to_string(),
+            file: file.clone(),
+            line,
+            kind,
+            scope: None,
+            file_scoped
+        };
+        for (name, symbol) in project.symbols(file).into_iter().flatten() {
+            let (Some(section), Some(&line)) =
+                (&symbol.section, asm.lines.labels.get(name))
+            else {
+                continue;
+            };
+            let is_executable = asm
+                .section_attributes
+                .get(section)
+                .cloned()
+                .unwrap_or_else(|| SectionAttributes::default_for(section))
+                .exec;
+            let kind = if is_executable {
+                TagKind::Function
+            } else {
+                TagKind::Data
+            };
+            let file_scoped = symbol.visibility == Visibility::Private;
+            tags.push(tag(name, line, kind, file_scoped));
+            for local in project.local_labels(file, name) {
+                let full_name = format!("{}{}", name, local);
+                if let Some(&line) = asm.lines.locals.get(&full_name) {
+                    tags.push(Tag {
+                        scope: Some((kind, name.clone())),
+                        ..tag(local, line, TagKind::Label, true)
+                    });
+                }
+            }
+        }
+        for macro_ in &asm.macros {
+            if let Some(&line) = asm.lines.macros.get(&macro_.name) {
+                tags.push(tag(&macro_.name, line, TagKind::Macro, false));
+            }
+        }
+        for define in &asm.defines {
+            if let Some(&line) = asm.lines.defines.get(define) {
+                let file_scoped = !asm.globals.contains(define);
+                tags.push(tag(define, line, TagKind::Define, file_scoped));
+            }
+        }
+        for structure in &asm.structs {
+            if let Some(&line) = asm.lines.structs.get(&structure.name) {
+                tags.push(tag(&structure.name, line, TagKind::Struct, false));
+            }
+            for field in structure
+                .fields
+                .iter()
+                .filter_map(|(name, _)| name.as_ref())
+            {
+                let full_name = format!("{}{}", structure.name, field);
+                if let Some(&line) = asm.lines.fields.get(&full_name) {
+                    tags.push(Tag {
+                        scope: Some((TagKind::Struct, structure.name.clone())),
+                        ..tag(&full_name, line, TagKind::Field, false)
+                    });
+                }
+            }
+        }
+    }
+    tags.sort_by(|a, b| {
+        (&a.name, &a.file, a.line).cmp(&(&b.name, &b.file, b.line))
+    });
+    tags
+}
+
+/// Writes `tags` in the extended ctags format, with file names relative to
+/// `directory`, where the tag file will be placed. Lines are addressed by
+/// number so that tags stay valid however the lines are spelled.
+pub fn write_ctags(
+    tags: &[Tag], directory: &Path, sink: &mut impl io::Write
+) -> io::Result<()> {
+    writeln!(sink, "!_TAG_FILE_FORMAT\t2\t/extended format/")?;
+    writeln!(sink, "!_TAG_FILE_SORTED\t1\t/0=unsorted, 1=sorted/")?;
+    writeln!(sink, "!_TAG_PROGRAM_NAME\tasmdoc\t//")?;
+    writeln!(
+        sink,
+        "!_TAG_PROGRAM_VERSION\t{}\t//",
+        env!("CARGO_PKG_VERSION")
+    )?;
+    for tag in tags {
+        write!(
+            sink,
+            "{}\t{}\t{};\"\tkind:{}\tline:{}",
+            tag.name,
+            relative_path(directory, &tag.file).display(),
+            tag.line,
+            tag.kind,
+            tag.line
+        )?;
+        if let Some((kind, name)) = &tag.scope {
+            write!(sink, "\t{}:{}", kind, name)?;
+        }
+        if tag.file_scoped {
+            write!(sink, "\tfile:")?;
+        }
+        writeln!(sink)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{tags, write_ctags};
+    use crate::generator::Generator;
+
+    const SOURCE: &str = "section .text
+global strlen
+
+strlen:
+    xor eax, eax
+.loop:
+    inc rax
+    jmp .loop
+
+helper:
+    ret
+
+section .data
+msg: db \"hi\", 0
+
+struc point
+    .x: resd 1
+endstruc
+";
+
+    #[test]
+    fn ctags_are_sorted_and_scoped() {
+        let generator = Generator::new();
+        let file = Path::new("/project/src/a.nasm");
+        let asm = generator.parse_source(file, SOURCE).unwrap();
+        let project = generator.build([(file.to_path_buf(), asm)]);
+
+        let mut output = Vec::new();
+        write_ctags(&tags(&project), Path::new("/project"), &mut output)
+            .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let lines = output
+            .lines()
+            .filter(|line| !line.starts_with("!_TAG_"))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            [
+                ".loop\tsrc/a.nasm\t6;\"\tkind:label\tline:6\tfunction:strlen\tfile:",
+                "helper\tsrc/a.nasm\t10;\"\tkind:function\tline:10\tfile:",
+                "msg\tsrc/a.nasm\t14;\"\tkind:data\tline:14\tfile:",
+                "point\tsrc/a.nasm\t16;\"\tkind:struct\tline:16",
+                "point.x\tsrc/a.nasm\t17;\"\tkind:field\tline:17\tstruct:point",
+                "strlen\tsrc/a.nasm\t4;\"\tkind:function\tline:4"
+            ]
+        );
+    }
+}
@@ -0,0 +1,400 @@
+// Copyright (C) 2024 Ethan Uppal. All rights reserved.
+
+//! Cross-checks a project against the symbol tables of the ELF objects
+//! assembled from it.
+
+use std::{
+    collections::BTreeMap,
+    error,
+    fmt::{self, Display},
+    fs, io,
+    path::{self, Component, Path, PathBuf}
+};
+
+use object::{
+    read::archive::ArchiveFile, FileKind, Object, ObjectSymbol, SymbolKind
+};
+
+use crate::{
+    assembly_project::{normalize, AssemblyProject},
+    lint::{Diagnostic, Lint}
+};
+
+#[derive(Debug)]
+pub enum VerifyError {
+    Read(PathBuf, io::Error),
+    Parse(PathBuf, object::Error),
+    /// The file is neither an ELF object nor an archive of them.
+    NotElf(PathBuf)
+}
+
+impl Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Read(path, error) => {
+                write!(f, "Could not read {}: {}", path.display(), error)
+            }
+            Self::Parse(path, error) => {
+                write!(f, "Could not parse {}: {}", path.display(), error)
+            }
+            Self::NotElf(path) => write!(
+                f,
+                "{} is not an ELF object or static archive",
+                path.display()
+            )
+        }
+    }
+}
+
+impl error::Error for VerifyError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::Read(_, error) => Some(error),
+            Self::Parse(_, error) => Some(error),
+            Self::NotElf(_) => None
+        }
+    }
+}
+
+/// The symbols an object or static archive makes visible to the linker.
+#[derive(Debug, Default)]
+pub struct ObjectSymbols {
+    pub path: PathBuf,
+    /// Size of each exported symbol, by name, which is zero when the
+    /// assembler was not told it.
+    pub exports: BTreeMap<String, u64>,
+    /// Names of the source files the object was assembled from, as recorded
+    /// by the assembler.
+    pub sources: Vec<String>
+}
+
+impl ObjectSymbols {
+    /// Reads the symbol table of the ELF object or static archive at `path`.
+    pub fn read(path: &Path) -> Result<Self, VerifyError> {
+        let data = fs::read(path)
+            .map_err(|error| VerifyError::Read(path.into(), error))?;
+        let parse_error = |error| VerifyError::Parse(path.into(), error);
+        let mut symbols = Self {
+            path: path.to_path_buf(),
+            ..Self::default()
+        };
+        let kind = FileKind::parse(&*data)
+            .map_err(|_| VerifyError::NotElf(path.into()))?;
+        match kind {
+            FileKind::Archive => {
+                let archive =
+                    ArchiveFile::parse(&*data).map_err(parse_error)?;
+                for member in archive.members() {
+                    let member = member.map_err(parse_error)?;
+                    symbols.add(member.data(&*data).map_err(parse_error)?)?;
+                }
+            }
+            FileKind::Elf32 | FileKind::Elf64 => symbols.add(&data)?,
+            _ => return Err(VerifyError::NotElf(path.into()))
+        }
+        Ok(symbols)
+    }
+
+    /// Adds the symbols of the ELF object `data`.
+    fn add(&mut self, data: &[u8]) -> Result<(), VerifyError> {
+        let file = object::File::parse(data)
+            .map_err(|error| VerifyError::Parse(self.path.clone(), error))?;
+        for symbol in file.symbols() {
+            let Ok(name) = symbol.name() else {
+                continue;
+            };
+            match symbol.kind() {
+                SymbolKind::File => self.sources.push(name.to_string()),
+                SymbolKind::Section => {}
+                _ if symbol.is_global() && !symbol.is_undefined() => {
+                    self.exports.insert(name.to_string(), symbol.size());
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// How closely the sources the object was assembled from name `file`,
+    /// as in [`source_match`], or `None` if none of them does. An object
+    /// that does not say could be assembled from any file, at closeness 0.
+    fn closeness(&self, file: &Path) -> Option<usize> {
+        if self.sources.is_empty() {
+            return Some(0);
+        }
+        self.sources
+            .iter()
+            .map(|source| source_match(file, source))
+            .max()
+            .filter(|&closeness| closeness > 0)
+    }
+}
+
+/// The objects `file` was assembled into: those whose sources name it most
+/// closely, so that `util.nasm` does not stand for `src/a/util.nasm` when
+/// another object names that exactly.
+fn built_from<'a>(
+    objects: &'a [ObjectSymbols], file: &Path
+) -> Vec<&'a ObjectSymbols> {
+    let closeness = objects
+        .iter()
+        .map(|object| object.closeness(file))
+        .collect::<Vec<_>>();
+    let best = closeness.iter().flatten().max().copied();
+    objects
+        .iter()
+        .zip(closeness)
+        .filter(|(_, closeness)| best.is_some() && *closeness == best)
+        .map(|(object, _)| object)
+        .collect()
+}
+
+/// How many trailing components of the project file `file` are named by
+/// `source`, a file name recorded by an assembler, or 0 if `source` cannot
+/// name `file`. A relative `source` may be relative to any directory.
+pub(crate) fn source_match(file: &Path, source: &str) -> usize {
+    let file = normalize(&path::absolute(file).unwrap_or(file.to_path_buf()));
+    let source = Path::new(source);
+    let source = if source.has_root() {
+        normalize(source)
+    } else {
+        // the directory the assembler ran in is unknown
+        source
+            .components()
+            .filter(|component| matches!(component, Component::Normal(_)))
+            .collect()
+    };
+    if file.ends_with(&source) {
+        source.components().count()
+    } else {
+        0
+    }
+}
+
+/// Whether `source`, a file name recorded by an assembler, could name the
+/// project file `file`.
+pub(crate) fn is_source(file: &Path, source: &str) -> bool {
+    source_match(file, source) > 0
+}
+
+/// Compares the globals of `project` with what `objects` export, returning
+/// diagnostics ordered by location.
+pub fn verify(
+    project: &AssemblyProject, objects: &[ObjectSymbols]
+) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+
+    for (file, asm) in project.files() {
+        let built = built_from(objects, file);
+        if built.is_empty() {
+            continue;
+        }
+        for global in &asm.globals {
+            let is_defined = asm.lines.labels.contains_key(global)
+                || asm.defines.contains(global);
+            let is_exported = built
+                .iter()
+                .any(|object| object.exports.contains_key(global));
+            if is_defined && asm.docs.contains_key(global) && !is_exported {
+                diagnostics.push(Diagnostic {
+                    file: file.clone(),
+                    line: asm.lines.globals.get(global).copied(),
+                    lint: Lint::NotExported(global.clone())
+                });
+            }
+        }
+    }
+
+    // each export once, from the first object exporting it
+    let mut exports = BTreeMap::new();
+    for object in objects {
+        for (name, &size) in &object.exports {
+            exports.entry(name).or_insert((size, &object.path));
+        }
+    }
+    for (name, (size, object)) in exports {
+        let files = project.definitions(name);
+        if files.is_empty() {
+            diagnostics.push(Diagnostic {
+                file: object.clone(),
+                line: None,
+                lint: Lint::UnknownExport(name.clone())
+            });
+            continue;
+        }
+        for file in files {
+            let asm = &project.files()[file];
+            let line = asm
+                .lines
+                .labels
+                .get(name)
+                .or(asm.lines.defines.get(name))
+                .copied();
+            if !asm.docs.contains_key(name) {
+                diagnostics.push(Diagnostic {
+                    file: file.clone(),
+                    line,
+                    lint: Lint::UndocumentedExport(name.clone())
+                });
+            }
+            // prefer the size recorded by an object built from this file
+            let size = built_from(objects, file)
+                .iter()
+                .find_map(|object| object.exports.get(name).copied())
+                .unwrap_or(size);
+            let computed = project
+                .symbol(file, name)
+                .filter(|symbol| !symbol.data.is_empty())
+                .and_then(|symbol| symbol.data_size());
+            if let Some(computed) = computed {
+                if size != 0 && size != computed as u64 {
+                    diagnostics.push(Diagnostic {
+                        file: file.clone(),
+                        line,
+                        lint: Lint::SizeMismatch {
+                            name: name.clone(),
+                            computed,
+                            object: size
+                        }
+                    });
+                }
+            }
+        }
+    }
+
+    diagnostics.sort_by(|a, b| (&a.file, a.line).cmp(&(&b.file, b.line)));
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use object::{
+        write, Architecture, BinaryFormat, Endianness, SectionKind,
+        SymbolFlags, SymbolKind, SymbolScope
+    };
+
+    use super::{source_match, verify, ObjectSymbols};
+    use crate::{generator::Generator, lint::Lint};
+
+    /// An x86-64 ELF object assembled from `sources`, exporting `exports`
+    /// by name and size, with a local label and an undefined extern too.
+    fn object(
+        directory: &Path, name: &str, sources: &[&str], exports: &[(&str, u64)]
+    ) -> ObjectSymbols {
+        let mut object = write::Object::new(
+            BinaryFormat::Elf,
+            Architecture::X86_64,
+            Endianness::Little
+        );
+        for source in sources {
+            object.add_file_symbol(source.as_bytes().to_vec());
+        }
+        let text = object.add_section(
+            Vec::new(),
+            b".text".to_vec(),
+            SectionKind::Text
+        );
+        object.append_section_data(text, &[0xc3; 16], 1);
+        let symbol = |name: &str, size, scope, section| write::Symbol {
+            name: name.as_bytes().to_vec(),
+            value: 0,
+            size,
+            kind: SymbolKind::Text,
+            scope,
+            weak: false,
+            section,
+            flags: SymbolFlags::None
+        };
+        object.add_symbol(symbol(
+            "local",
+            1,
+            SymbolScope::Compilation,
+            write::SymbolSection::Section(text)
+        ));
+        object.add_symbol(symbol(
+            "puts",
+            0,
+            SymbolScope::Linkage,
+            write::SymbolSection::Undefined
+        ));
+        for &(name, size) in exports {
+            object.add_symbol(symbol(
+                name,
+                size,
+                SymbolScope::Linkage,
+                write::SymbolSection::Section(text)
+            ));
+        }
+        let path = directory.join(name);
+        fs::write(&path, object.write().unwrap()).unwrap();
+        ObjectSymbols::read(&path).unwrap()
+    }
+
+    #[test]
+    fn reads_exports_and_sources() {
+        let directory = tempfile::tempdir().unwrap();
+        let object = object(
+            directory.path(),
+            "util.o",
+            &["src/a/util.nasm"],
+            &[("foo", 1), ("bar", 0)]
+        );
+        assert_eq!(object.sources, ["src/a/util.nasm"]);
+        assert_eq!(
+            object.exports.into_iter().collect::<Vec<_>>(),
+            [("bar".to_string(), 0), ("foo".to_string(), 1)]
+        );
+    }
+
+    #[test]
+    fn sources_name_trailing_components() {
+        let file = Path::new("/p/src/a/util.nasm");
+        assert_eq!(source_match(file, "util.nasm"), 1);
+        assert_eq!(source_match(file, "./a/util.nasm"), 2);
+        assert_eq!(source_match(file, "../src/a/util.nasm"), 3);
+        assert!(source_match(file, "/p/src/a/util.nasm") > 3);
+        assert_eq!(source_match(file, "src/b/util.nasm"), 0);
+        assert_eq!(source_match(file, "/q/src/a/util.nasm"), 0);
+        // a relative project file is not named by a longer source
+        assert_eq!(source_match(Path::new("util.nasm"), "/q/b/util.nasm"), 0);
+    }
+
+    #[test]
+    fn exports_are_checked_against_closest_objects() {
+        let generator = Generator::new();
+        let files =
+            [("/p/src/a/util.nasm", "foo"), ("/p/src/b/util.nasm", "bar")];
+        let files = files.map(|(file, name)| {
+            let source = format!(
+                "section .text\nglobal {0}\n;; Documented.\n{0}:\n    ret\n",
+                name
+            );
+            let file = Path::new(file);
+            (file.into(), generator.parse_source(file, &source).unwrap())
+        });
+        let project = generator.build(files);
+
+        // `util.o` names either file, but `a.o` names the first exactly and
+        // lacks its export
+        let directory = tempfile::tempdir().unwrap();
+        let objects = [
+            object(directory.path(), "a.o", &["src/a/util.nasm"], &[]),
+            object(
+                directory.path(),
+                "util.o",
+                &["util.nasm"],
+                &[("foo", 0), ("bar", 0)]
+            )
+        ];
+        let diagnostics = verify(&project, &objects);
+        assert_eq!(diagnostics.len(), 1, "{:?}", diagnostics);
+        assert_eq!(diagnostics[0].file, Path::new("/p/src/a/util.nasm"));
+        assert_eq!(diagnostics[0].line, Some(2));
+        assert_eq!(diagnostics[0].lint, Lint::NotExported("foo".into()));
+
+        // with only `util.o`, it stands for both
+        assert!(verify(&project, &objects[1..]).is_empty());
+    }
+}
@@ -0,0 +1,179 @@
+// Copyright (C) 2024 Ethan Uppal. All rights reserved.
+
+use std::{fs, path::Path, thread};
+
+use asmdoc::{generator::Generator, lsp};
+use lsp_server::{Connection, Message, Notification, Request, RequestId};
+use lsp_types::Url;
+use serde_json::{json, Value};
+
+const LIB: &str = "section .text
+global strlen
+
+;; Computes the length of a string.
+strlen:
+    xor eax, eax
+.loop:
+    inc rax
+    jmp .loop
+";
+
+const MAIN: &str = "section .text
+global _start
+extern strlen
+_start:
+    call strlen
+    ret
+";
+
+/// The client side of an in-memory session.
+struct Client {
+    connection: Connection,
+    next_id: i32
+}
+
+impl Client {
+    fn request(&mut self, method: &str, params: Value) -> Value {
+        self.next_id += 1;
+        let id = RequestId::from(self.next_id);
+        self.connection
+            .sender
+            .send(Message::Request(Request::new(
+                id.clone(),
+                method.into(),
+                params
+            )))
+            .unwrap();
+        for message in &self.connection.receiver {
+            if let Message::Response(response) = message {
+                if response.id == id {
+                    assert!(response.error.is_none(), "{:?}", response.error);
+                    return response.result.unwrap_or(Value::Null);
+                }
+            }
+        }
+        panic!("server hung up before responding to {}", method);
+    }
+
+    fn notify(&self, method: &str, params: Value) {
+        self.connection
+            .sender
+            .send(Message::Notification(Notification::new(
+                method.into(),
+                params
+            )))
+            .unwrap();
+    }
+}
+
+fn uri(file: &Path) -> String {
+    Url::from_file_path(file).unwrap().to_string()
+}
+
+fn position(file: &Path, line: u32, character: u32) -> Value {
+    json!({
+        "textDocument": { "uri": uri(file) },
+        "position": { "line": line, "character": character }
+    })
+}
+
+fn range(line: u32, start: u32, end: u32) -> Value {
+    json!({
+        "start": { "line": line, "character": start },
+        "end": { "line": line, "character": end }
+    })
+}
+
+#[test]
+fn session_over_memory_connection() {
+    let directory = tempfile::tempdir().unwrap();
+    let root = fs::canonicalize(directory.path()).unwrap();
+    let lib = root.join("lib.nasm");
+    let main = root.join("main.nasm");
+    fs::write(&lib, LIB).unwrap();
+    fs::write(&main, MAIN).unwrap();
+    let generator = Generator::new().input(&root);
+
+    let (server, client) = Connection::memory();
+    thread::scope(|scope| {
+        let session = scope.spawn(|| lsp::serve(&generator, &server));
+        let mut client = Client {
+            connection: client,
+            next_id: 0
+        };
+
+        let initialized = client.request(
+            "initialize",
+            json!({ "capabilities": {}, "processId": null, "rootUri": null })
+        );
+        assert_eq!(initialized["capabilities"]["hoverProvider"], true);
+        client.notify("initialized", json!({}));
+
+        // an unsaved second call
+        client.notify(
+            "textDocument/didOpen",
+            json!({
+                "textDocument": {
+                    "uri": uri(&main),
+                    "languageId": "nasm",
+                    "version": 1,
+                    "text": MAIN.replace("    ret", "    call strlen\n    ret")
+                }
+            })
+        );
+
+        let hover =
+            client.request("textDocument/hover", position(&main, 4, 10));
+        let hover = hover["contents"]["value"].as_str().unwrap();
+        assert!(hover.contains("global routine in text"), "{}", hover);
+        assert!(hover.contains("Computes the length of a string."));
+        assert!(hover.contains(&lib.display().to_string()));
+
+        let definition =
+            client.request("textDocument/definition", position(&main, 4, 10));
+        assert_eq!(
+            definition,
+            json!([{ "uri": uri(&lib), "range": range(4, 0, 6) }])
+        );
+        let definition =
+            client.request("textDocument/definition", position(&lib, 8, 9));
+        assert_eq!(
+            definition,
+            json!([{ "uri": uri(&lib), "range": range(6, 0, 5) }])
+        );
+
+        let mut references = position(&main, 4, 10);
+        references["context"] = json!({ "includeDeclaration": true });
+        let references = client.request("textDocument/references", references);
+        assert_eq!(
+            references,
+            json!([
+                { "uri": uri(&lib), "range": range(1, 7, 13) },
+                { "uri": uri(&lib), "range": range(4, 0, 6) },
+                { "uri": uri(&main), "range": range(2, 7, 13) },
+                { "uri": uri(&main), "range": range(4, 9, 15) },
+                { "uri": uri(&main), "range": range(5, 9, 15) }
+            ])
+        );
+
+        let symbols = client.request(
+            "textDocument/documentSymbol",
+            json!({ "textDocument": { "uri": uri(&lib) } })
+        );
+        let symbols = symbols.as_array().unwrap();
+        assert_eq!(symbols.len(), 1);
+        assert_eq!(symbols[0]["name"], "strlen");
+        assert_eq!(symbols[0]["children"][0]["name"], ".loop");
+
+        let symbols =
+            client.request("workspace/symbol", json!({ "query": "STR" }));
+        let symbols = symbols.as_array().unwrap();
+        assert_eq!(symbols.len(), 1);
+        assert_eq!(symbols[0]["name"], "strlen");
+        assert_eq!(symbols[0]["location"]["uri"], uri(&lib));
+
+        assert_eq!(client.request("shutdown", Value::Null), Value::Null);
+        client.notify("exit", Value::Null);
+        session.join().unwrap().unwrap();
+    });
+}